@@ -0,0 +1,275 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::Range;
+
+/// An ad from the Ad Library.
+///
+/// The common attributes are lifted into typed fields, tolerating the
+/// different shapes returned by the website scraper and the Graph API. Any
+/// field that is missing or malformed is left empty; the untouched response
+/// object is always available in `raw`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FacebookAd {
+    pub ad_archive_id: Option<String>,
+    pub page_id: Option<String>,
+    pub page_name: Option<String>,
+    pub start_date: Option<DateTime<Utc>>,
+    pub end_date: Option<DateTime<Utc>>,
+    pub is_active: Option<bool>,
+    /// Spend bucket, in `currency`.
+    pub spend: Option<Range>,
+    pub impressions: Option<Range>,
+    pub currency: Option<String>,
+    /// Lowercase platform names, e.g. `["facebook", "instagram"]`.
+    pub publisher_platforms: Vec<String>,
+    pub body: Option<String>,
+    pub title: Option<String>,
+    pub link_url: Option<String>,
+    pub cta_text: Option<String>,
+    pub snapshot_url: Option<String>,
+    pub raw: Value,
+}
+
+impl FacebookAd {
+    pub fn from_value(raw: Value) -> Self {
+        let snapshot = raw.get("snapshot");
+        let snapshot_str = |key: &str| snapshot.and_then(|s| string_at(s, key));
+
+        let is_active = raw.get("isActive").and_then(Value::as_bool).or_else(|| {
+            // The Graph API only reports a stop time once delivery has ended.
+            raw.get("ad_delivery_start_time")
+                .map(|_| raw.get("ad_delivery_stop_time").is_none_or(Value::is_null))
+        });
+
+        let publisher_platforms = raw
+            .get("publisherPlatform")
+            .or_else(|| raw.get("publisher_platforms"))
+            .and_then(Value::as_array)
+            .map(|platforms| {
+                platforms
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_ascii_lowercase)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let body = snapshot
+            .and_then(|s| s.get("body"))
+            .and_then(|body| {
+                body.as_str()
+                    .map(str::to_string)
+                    .or_else(|| string_at(body, "text"))
+                    .or_else(|| body.get("markup").and_then(|markup| string_at(markup, "__html")))
+            })
+            .filter(|body| !body.is_empty())
+            .or_else(|| first_string(&raw, "ad_creative_bodies"));
+
+        FacebookAd {
+            ad_archive_id: string_at(&raw, "adArchiveID").or_else(|| string_at(&raw, "id")),
+            page_id: string_at(&raw, "pageID").or_else(|| string_at(&raw, "page_id")),
+            page_name: string_at(&raw, "pageName")
+                .or_else(|| string_at(&raw, "page_name"))
+                .or_else(|| snapshot_str("page_name")),
+            start_date: raw
+                .get("startDate")
+                .or_else(|| raw.get("ad_delivery_start_time"))
+                .and_then(parse_date),
+            end_date: raw
+                .get("endDate")
+                .or_else(|| raw.get("ad_delivery_stop_time"))
+                .and_then(parse_date),
+            is_active,
+            spend: spend_range(&raw),
+            impressions: impressions_range(&raw),
+            currency: string_at(&raw, "currency"),
+            publisher_platforms,
+            body,
+            title: snapshot_str("title").or_else(|| first_string(&raw, "ad_creative_link_titles")),
+            link_url: snapshot_str("link_url"),
+            cta_text: snapshot_str("cta_text"),
+            snapshot_url: string_at(&raw, "ad_snapshot_url"),
+            raw,
+        }
+    }
+}
+
+impl From<Value> for FacebookAd {
+    fn from(raw: Value) -> Self {
+        FacebookAd::from_value(raw)
+    }
+}
+
+/// A non-empty string or number at `key`, as a string.
+fn string_at(value: &Value, key: &str) -> Option<String> {
+    match value.get(key)? {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn first_string(value: &Value, key: &str) -> Option<String> {
+    value
+        .get(key)?
+        .as_array()?
+        .iter()
+        .find_map(Value::as_str)
+        .map(str::to_string)
+}
+
+/// Dates come back as unix timestamps from the scraper and as `YYYY-MM-DD`
+/// or ISO 8601 strings from the Graph API.
+fn parse_date(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::Number(n) => DateTime::from_timestamp(n.as_i64()?, 0),
+        Value::String(s) => DateTime::parse_from_rfc3339(s)
+            .or_else(|_| DateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%z"))
+            .map(|date| date.with_timezone(&Utc))
+            .ok()
+            .or_else(|| {
+                NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                    .ok()?
+                    .and_hms_opt(0, 0, 0)
+                    .map(|date| date.and_utc())
+            })
+            .or_else(|| DateTime::from_timestamp(s.parse().ok()?, 0)),
+        _ => None,
+    }
+}
+
+fn spend_range(raw: &Value) -> Option<Range> {
+    Range::parse(raw.get("spend")?).map(|range| range.with_currency(string_at(raw, "currency")))
+}
+
+fn impressions_range(raw: &Value) -> Option<Range> {
+    raw.get("impressionsWithIndex")
+        .and_then(|i| i.get("impressionsText"))
+        .and_then(Range::parse)
+        .or_else(|| Range::parse(raw.get("impressions")?))
+}
+
+/// Columns derived from an ad's spend and impressions buckets.
+pub(crate) const RANGE_COLUMNS: [&str; 4] = ["spend_lower", "spend_upper", "impressions_lower", "impressions_upper"];
+
+/// Whether a raw row carries spend or impressions, i.e. should get [`RANGE_COLUMNS`].
+pub(crate) fn has_range_fields(raw: &Value) -> bool {
+    ["spend", "impressions", "impressionsWithIndex"]
+        .iter()
+        .any(|key| raw.get(key).is_some())
+}
+
+/// Values for [`RANGE_COLUMNS`], blank where a bound is unknown.
+pub(crate) fn range_column_values(raw: &Value) -> [String; 4] {
+    let bound = |value: Option<u64>| value.map(|v| v.to_string()).unwrap_or_default();
+    let spend = spend_range(raw);
+    let impressions = impressions_range(raw);
+    [
+        bound(spend.as_ref().and_then(|r| r.lower)),
+        bound(spend.as_ref().and_then(|r| r.upper)),
+        bound(impressions.as_ref().and_then(|r| r.lower)),
+        bound(impressions.as_ref().and_then(|r| r.upper)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_scraper_value() {
+        let raw = json!({
+            "adArchiveID": "123",
+            "pageID": "456",
+            "pageName": "Test Page",
+            "startDate": 1700000000,
+            "endDate": 1700086400,
+            "isActive": false,
+            "spend": "<100",
+            "currency": "USD",
+            "impressionsWithIndex": {"impressionsText": "1K-5K", "impressionsIndex": 2},
+            "publisherPlatform": ["FACEBOOK", "INSTAGRAM"],
+            "snapshot": {
+                "body": {"markup": {"__html": "Buy heat pumps"}},
+                "title": "Heat pumps",
+                "link_url": "https://example.com",
+                "cta_text": "Learn more"
+            }
+        });
+        let ad = FacebookAd::from_value(raw.clone());
+
+        assert_eq!(ad.ad_archive_id.as_deref(), Some("123"));
+        assert_eq!(ad.page_id.as_deref(), Some("456"));
+        assert_eq!(ad.page_name.as_deref(), Some("Test Page"));
+        assert_eq!(ad.start_date.unwrap().timestamp(), 1700000000);
+        assert_eq!(ad.end_date.unwrap().timestamp(), 1700086400);
+        assert_eq!(ad.is_active, Some(false));
+        let spend = ad.spend.as_ref().unwrap();
+        assert_eq!((spend.lower, spend.upper), (Some(0), Some(99)));
+        assert_eq!(spend.currency.as_deref(), Some("USD"));
+        let impressions = ad.impressions.as_ref().unwrap();
+        assert_eq!((impressions.lower, impressions.upper), (Some(1_000), Some(5_000)));
+        assert_eq!(impressions.midpoint(), Some(3_000.0));
+        assert_eq!(ad.currency.as_deref(), Some("USD"));
+        assert_eq!(ad.publisher_platforms, vec!["facebook", "instagram"]);
+        assert_eq!(ad.body.as_deref(), Some("Buy heat pumps"));
+        assert_eq!(ad.title.as_deref(), Some("Heat pumps"));
+        assert_eq!(ad.link_url.as_deref(), Some("https://example.com"));
+        assert_eq!(ad.cta_text.as_deref(), Some("Learn more"));
+        assert_eq!(ad.raw, raw);
+    }
+
+    #[test]
+    fn test_from_graph_api_value() {
+        let ad = FacebookAd::from_value(json!({
+            "id": "789",
+            "page_id": "456",
+            "page_name": "Test Page",
+            "ad_delivery_start_time": "2024-01-02",
+            "ad_creative_bodies": ["Vote for us"],
+            "ad_creative_link_titles": ["Election"],
+            "ad_snapshot_url": "https://www.facebook.com/ads/archive/render_ad/?id=789",
+            "spend": {"lower_bound": "100", "upper_bound": "199"},
+            "impressions": {"lower_bound": "1000"},
+            "publisher_platforms": ["facebook"]
+        }));
+
+        assert_eq!(ad.ad_archive_id.as_deref(), Some("789"));
+        assert_eq!(ad.start_date.unwrap().to_rfc3339(), "2024-01-02T00:00:00+00:00");
+        assert_eq!(ad.end_date, None);
+        assert_eq!(ad.is_active, Some(true));
+        assert_eq!(ad.spend, Some(Range::new(Some(100), Some(199))));
+        assert_eq!(ad.impressions, Some(Range::new(Some(1_000), None)));
+        assert_eq!(ad.body.as_deref(), Some("Vote for us"));
+        assert_eq!(ad.title.as_deref(), Some("Election"));
+        assert_eq!(ad.publisher_platforms, vec!["facebook"]);
+    }
+
+    #[test]
+    fn test_from_value_tolerates_missing_and_malformed_fields() {
+        let ad = FacebookAd::from_value(json!({"ad_id": "1", "startDate": "not a date", "isActive": "yes", "snapshot": null}));
+
+        assert_eq!(ad.ad_archive_id, None);
+        assert_eq!(ad.start_date, None);
+        assert_eq!(ad.is_active, None);
+        assert!(ad.publisher_platforms.is_empty());
+        assert_eq!(ad.raw["ad_id"], "1");
+
+        let ad = FacebookAd::from_value(json!("not an object"));
+        assert_eq!(ad.page_id, None);
+    }
+
+    #[test]
+    fn test_range_column_values() {
+        let raw = json!({"spend": {"lower_bound": "100", "upper_bound": "199"}, "impressionsWithIndex": {"impressionsText": ">1M"}});
+        assert!(has_range_fields(&raw));
+        assert_eq!(range_column_values(&raw), ["100", "199", "1000000", ""]);
+
+        let raw = json!({"pageName": "Test"});
+        assert!(!has_range_fields(&raw));
+        assert_eq!(range_column_values(&raw), ["", "", "", ""]);
+    }
+}
@@ -0,0 +1,449 @@
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+
+use crate::{ActiveStatus, AdQuery, AdType, FacebookAd, FacebookTotem, KeywordMatch, MediaType, PublisherPlatform, RateLimiter};
+
+/// A source of Ad Library results.
+///
+/// Every backend returns [`FacebookAd`]s so callers can switch between them
+/// without changing how results are handled.
+#[async_trait]
+pub trait AdLibraryBackend: Send + Sync {
+    /// All ads run by a page that match `query`.
+    async fn ads_for_page(&self, page_id: &str, query: &AdQuery) -> Result<Vec<FacebookAd>>;
+
+    /// All ads whose text matches `keywords`, across every page.
+    async fn ads_for_keywords(&self, keywords: &str, keyword_match: KeywordMatch, query: &AdQuery) -> Result<Vec<FacebookAd>>;
+
+    /// [`AdLibraryBackend::ads_for_page`] as a stream, yielding ads as they are
+    /// fetched. The default implementation fetches everything up front.
+    fn ads_stream<'a>(&'a self, page_id: &'a str, query: &'a AdQuery) -> BoxStream<'a, Result<FacebookAd>> {
+        stream::once(self.ads_for_page(page_id, query))
+            .map_ok(|ads| stream::iter(ads.into_iter().map(Ok)))
+            .try_flatten()
+            .boxed()
+    }
+
+    /// [`AdLibraryBackend::ads_for_keywords`] as a stream, like [`AdLibraryBackend::ads_stream`].
+    fn keywords_stream<'a>(
+        &'a self,
+        keywords: &'a str,
+        keyword_match: KeywordMatch,
+        query: &'a AdQuery,
+    ) -> BoxStream<'a, Result<FacebookAd>> {
+        stream::once(self.ads_for_keywords(keywords, keyword_match, query))
+            .map_ok(|ads| stream::iter(ads.into_iter().map(Ok)))
+            .try_flatten()
+            .boxed()
+    }
+}
+
+/// Scrapes the Ad Library website's internal `search_ads` endpoint. Needs no
+/// credentials, but breaks whenever Facebook changes the endpoint.
+pub struct ScraperBackend {
+    totem: Arc<FacebookTotem>,
+}
+
+impl ScraperBackend {
+    pub fn new() -> Result<Self> {
+        Ok(Self::with_client(FacebookTotem::new()?))
+    }
+
+    /// Scrape through an existing client, sharing its cookies and session.
+    pub fn with_client(totem: impl Into<Arc<FacebookTotem>>) -> Self {
+        Self { totem: totem.into() }
+    }
+}
+
+#[async_trait]
+impl AdLibraryBackend for ScraperBackend {
+    async fn ads_for_page(&self, page_id: &str, query: &AdQuery) -> Result<Vec<FacebookAd>> {
+        self.totem.get_ads_from_id(page_id, query).await
+    }
+
+    async fn ads_for_keywords(&self, keywords: &str, keyword_match: KeywordMatch, query: &AdQuery) -> Result<Vec<FacebookAd>> {
+        self.totem.search_ads_by_keyword(keywords, keyword_match, query).await
+    }
+
+    fn ads_stream<'a>(&'a self, page_id: &'a str, query: &'a AdQuery) -> BoxStream<'a, Result<FacebookAd>> {
+        self.totem.ads_stream(page_id, query).boxed()
+    }
+
+    fn keywords_stream<'a>(
+        &'a self,
+        keywords: &'a str,
+        keyword_match: KeywordMatch,
+        query: &'a AdQuery,
+    ) -> BoxStream<'a, Result<FacebookAd>> {
+        self.totem.keyword_ads_stream(keywords, keyword_match, query).boxed()
+    }
+}
+
+const GRAPH_API_BASE_URL: &str = "https://graph.facebook.com";
+const GRAPH_API_VERSION: &str = "v21.0";
+const GRAPH_API_PAGE_SIZE: usize = 100;
+
+/// Fields requested from `ads_archive` when none are configured.
+pub const GRAPH_API_DEFAULT_FIELDS: &[&str] = &[
+    "id",
+    "ad_creation_time",
+    "ad_creative_bodies",
+    "ad_creative_link_captions",
+    "ad_creative_link_descriptions",
+    "ad_creative_link_titles",
+    "ad_delivery_start_time",
+    "ad_delivery_stop_time",
+    "ad_snapshot_url",
+    "bylines",
+    "currency",
+    "impressions",
+    "languages",
+    "page_id",
+    "page_name",
+    "publisher_platforms",
+    "spend",
+];
+
+/// Queries the official Ad Library API (`/ads_archive`) with a Graph API
+/// access token.
+///
+/// Sorting is not supported by the API, so the query's sort order is ignored.
+pub struct GraphApiBackend {
+    client: Client,
+    access_token: String,
+    base_url: String,
+    api_version: String,
+    fields: Vec<String>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphApiResponse {
+    #[serde(default)]
+    data: Vec<Value>,
+    paging: Option<GraphApiPaging>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphApiPaging {
+    cursors: Option<GraphApiCursors>,
+    next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphApiCursors {
+    after: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphApiErrorResponse {
+    error: GraphApiError,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphApiError {
+    message: String,
+    #[serde(rename = "type", default)]
+    error_type: Option<String>,
+    #[serde(default)]
+    code: Option<i64>,
+}
+
+impl GraphApiBackend {
+    pub fn new(access_token: &str) -> Self {
+        Self {
+            client: Client::new(),
+            access_token: access_token.to_string(),
+            base_url: GRAPH_API_BASE_URL.to_string(),
+            api_version: GRAPH_API_VERSION.to_string(),
+            fields: GRAPH_API_DEFAULT_FIELDS.iter().map(|f| f.to_string()).collect(),
+            rate_limiter: None,
+        }
+    }
+
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Send requests somewhere other than `https://graph.facebook.com`.
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn api_version(mut self, api_version: &str) -> Self {
+        self.api_version = api_version.to_string();
+        self
+    }
+
+    pub fn fields<I, S>(mut self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.fields = fields.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Wait for `limiter` before every request.
+    pub fn rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Page through `ads_archive` with the `after` cursor, yielding each page's
+    /// ads before the next one is requested.
+    fn ads_archive_stream<'a>(&'a self, search_params: Vec<(&'static str, String)>, query: &AdQuery) -> BoxStream<'a, Result<FacebookAd>> {
+        let cursor = AdsArchiveCursor {
+            search_params,
+            filter_params: graph_api_params(query),
+            max_ads: query.get_max_ads(),
+            fetched: 0,
+            after: None,
+            // Nothing to fetch, so don't ask for `limit=0`.
+            done: query.get_max_ads() == Some(0),
+        };
+
+        stream::try_unfold(cursor, move |mut cursor| async move {
+            if cursor.done {
+                return anyhow::Ok(None);
+            }
+            let ads = self.fetch_ads_archive_page(&mut cursor).await?;
+            Ok(Some((stream::iter(ads.into_iter().map(Ok)), cursor)))
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    async fn fetch_ads_archive_page(&self, cursor: &mut AdsArchiveCursor) -> Result<Vec<FacebookAd>> {
+        let url = format!("{}/{}/ads_archive", self.base_url, self.api_version);
+        let fields = self.fields.join(",");
+        let limit = cursor
+            .max_ads
+            .map(|max| max.saturating_sub(cursor.fetched).min(GRAPH_API_PAGE_SIZE))
+            .unwrap_or(GRAPH_API_PAGE_SIZE)
+            .to_string();
+
+        let mut params: Vec<(&str, &str)> = vec![
+            ("access_token", self.access_token.as_str()),
+            ("fields", fields.as_str()),
+            ("limit", limit.as_str()),
+        ];
+        params.extend(cursor.filter_params.iter().map(|(k, v)| (*k, v.as_str())));
+        params.extend(cursor.search_params.iter().map(|(k, v)| (*k, v.as_str())));
+        if let Some(after) = &cursor.after {
+            params.push(("after", after.as_str()));
+        }
+
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.acquire().await;
+        }
+        let response = self.client.get(&url).query(&params).send().await?;
+        let status = response.status();
+        let text = response.text().await?;
+        let page = parse_graph_api_response(&text, status.is_success())?;
+
+        let next_cursor = page
+            .paging
+            .filter(|paging| paging.next.is_some())
+            .and_then(|paging| paging.cursors)
+            .and_then(|cursors| cursors.after);
+        let mut ads: Vec<FacebookAd> = page.data.into_iter().map(FacebookAd::from_value).collect();
+        if let Some(max) = cursor.max_ads {
+            ads.truncate(max.saturating_sub(cursor.fetched));
+        }
+        cursor.fetched += ads.len();
+
+        match next_cursor {
+            // A cursor that didn't move would fetch the same page forever.
+            Some(next)
+                if !ads.is_empty()
+                    && cursor.max_ads.is_none_or(|max| cursor.fetched < max)
+                    && cursor.after.as_deref() != Some(next.as_str()) =>
+            {
+                cursor.after = Some(next);
+            }
+            _ => cursor.done = true,
+        }
+        Ok(ads)
+    }
+
+    fn keyword_params(keywords: &str, keyword_match: KeywordMatch) -> Vec<(&'static str, String)> {
+        let search_type = match keyword_match {
+            KeywordMatch::ExactPhrase => "KEYWORD_EXACT_PHRASE",
+            KeywordMatch::AnyWord => "KEYWORD_UNORDERED",
+        };
+        vec![
+            ("search_terms", keywords.to_string()),
+            ("search_type", search_type.to_string()),
+        ]
+    }
+}
+
+/// Where a paginated `ads_archive` pull has got to.
+struct AdsArchiveCursor {
+    search_params: Vec<(&'static str, String)>,
+    filter_params: Vec<(&'static str, String)>,
+    max_ads: Option<usize>,
+    fetched: usize,
+    after: Option<String>,
+    done: bool,
+}
+
+#[async_trait]
+impl AdLibraryBackend for GraphApiBackend {
+    async fn ads_for_page(&self, page_id: &str, query: &AdQuery) -> Result<Vec<FacebookAd>> {
+        self.ads_stream(page_id, query).try_collect().await
+    }
+
+    async fn ads_for_keywords(&self, keywords: &str, keyword_match: KeywordMatch, query: &AdQuery) -> Result<Vec<FacebookAd>> {
+        self.keywords_stream(keywords, keyword_match, query).try_collect().await
+    }
+
+    fn ads_stream<'a>(&'a self, page_id: &'a str, query: &'a AdQuery) -> BoxStream<'a, Result<FacebookAd>> {
+        let page_ids = Value::from(vec![page_id]).to_string();
+        self.ads_archive_stream(vec![("search_page_ids", page_ids)], query)
+    }
+
+    fn keywords_stream<'a>(
+        &'a self,
+        keywords: &'a str,
+        keyword_match: KeywordMatch,
+        query: &'a AdQuery,
+    ) -> BoxStream<'a, Result<FacebookAd>> {
+        self.ads_archive_stream(Self::keyword_params(keywords, keyword_match), query)
+    }
+}
+
+fn parse_graph_api_response(text: &str, is_success: bool) -> Result<GraphApiResponse> {
+    if let Ok(error) = serde_json::from_str::<GraphApiErrorResponse>(text) {
+        let error = error.error;
+        return Err(anyhow!(
+            "Graph API error{}{}: {}",
+            error.error_type.map(|t| format!(" {}", t)).unwrap_or_default(),
+            error.code.map(|c| format!(" (code {})", c)).unwrap_or_default(),
+            error.message
+        ));
+    }
+    if !is_success {
+        return Err(anyhow!("Graph API request failed: {}", text));
+    }
+    Ok(serde_json::from_str(text)?)
+}
+
+/// Translate an [`AdQuery`] into `ads_archive` parameters.
+fn graph_api_params(query: &AdQuery) -> Vec<(&'static str, String)> {
+    let countries: Vec<&str> = if query.get_countries().is_empty() {
+        vec!["ALL"]
+    } else {
+        query.get_countries().iter().map(String::as_str).collect()
+    };
+
+    let mut params = vec![
+        ("ad_reached_countries", serde_json::to_string(&countries).unwrap_or_default()),
+        ("ad_active_status", graph_active_status(query.get_active_status()).to_string()),
+        ("ad_type", graph_ad_type(query.get_ad_type()).to_string()),
+        ("media_type", graph_media_type(query.get_media_type()).to_string()),
+    ];
+
+    if !query.get_publisher_platforms().is_empty() {
+        let platforms: Vec<&str> = query
+            .get_publisher_platforms()
+            .iter()
+            .map(|p| graph_publisher_platform(*p))
+            .collect();
+        params.push(("publisher_platforms", serde_json::to_string(&platforms).unwrap_or_default()));
+    }
+
+    let (min, max) = query.get_delivery_date_range();
+    if let Some(min) = min {
+        params.push(("ad_delivery_date_min", min.format("%Y-%m-%d").to_string()));
+    }
+    if let Some(max) = max {
+        params.push(("ad_delivery_date_max", max.format("%Y-%m-%d").to_string()));
+    }
+
+    params
+}
+
+fn graph_active_status(status: ActiveStatus) -> &'static str {
+    match status {
+        ActiveStatus::All => "ALL",
+        ActiveStatus::Active => "ACTIVE",
+        ActiveStatus::Inactive => "INACTIVE",
+    }
+}
+
+fn graph_ad_type(ad_type: AdType) -> &'static str {
+    match ad_type {
+        AdType::All => "ALL",
+        AdType::PoliticalAndIssueAds => "POLITICAL_AND_ISSUE_ADS",
+        AdType::Housing => "HOUSING_ADS",
+        AdType::Employment => "EMPLOYMENT_ADS",
+        AdType::Credit => "CREDIT_ADS",
+    }
+}
+
+fn graph_media_type(media_type: MediaType) -> &'static str {
+    match media_type {
+        MediaType::All => "ALL",
+        MediaType::Image => "IMAGE",
+        MediaType::Video => "VIDEO",
+        MediaType::Meme => "MEME",
+        MediaType::None => "NONE",
+    }
+}
+
+fn graph_publisher_platform(platform: PublisherPlatform) -> &'static str {
+    match platform {
+        PublisherPlatform::Facebook => "FACEBOOK",
+        PublisherPlatform::Instagram => "INSTAGRAM",
+        PublisherPlatform::AudienceNetwork => "AUDIENCE_NETWORK",
+        PublisherPlatform::Messenger => "MESSENGER",
+        PublisherPlatform::WhatsApp => "WHATSAPP",
+        PublisherPlatform::Threads => "THREADS",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_graph_api_params() {
+        let query = AdQuery::new()
+            .countries(["US", "GB"])
+            .active_status(ActiveStatus::Active)
+            .ad_type(AdType::PoliticalAndIssueAds)
+            .publisher_platforms([PublisherPlatform::Instagram])
+            .delivery_date_range(chrono::NaiveDate::from_ymd_opt(2024, 1, 1), None)
+            .unwrap();
+        let params = graph_api_params(&query);
+        let get = |key: &str| params.iter().find(|(k, _)| *k == key).map(|(_, v)| v.as_str());
+
+        assert_eq!(get("ad_reached_countries"), Some(r#"["US","GB"]"#));
+        assert_eq!(get("ad_active_status"), Some("ACTIVE"));
+        assert_eq!(get("ad_type"), Some("POLITICAL_AND_ISSUE_ADS"));
+        assert_eq!(get("publisher_platforms"), Some(r#"["INSTAGRAM"]"#));
+        assert_eq!(get("ad_delivery_date_min"), Some("2024-01-01"));
+        assert_eq!(get("ad_delivery_date_max"), None);
+
+        let params = graph_api_params(&AdQuery::new());
+        assert!(params.contains(&("ad_reached_countries", r#"["ALL"]"#.to_string())));
+    }
+
+    #[test]
+    fn test_parse_graph_api_error() {
+        let text = r#"{"error":{"message":"Invalid OAuth access token.","type":"OAuthException","code":190}}"#;
+        let err = parse_graph_api_response(text, false).unwrap_err().to_string();
+        assert!(err.contains("OAuthException"));
+        assert!(err.contains("190"));
+        assert!(err.contains("Invalid OAuth access token."));
+    }
+}
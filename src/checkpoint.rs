@@ -0,0 +1,267 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Write};
+use std::path::Path;
+
+use crate::FacebookError;
+
+/// How a multi mode target ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetStatus {
+    /// The page's ads were written.
+    Done,
+    /// The page was found but hasn't run any ads.
+    NoAds,
+    /// The page loaded but no page ID could be found in it.
+    IdNotFound,
+    /// A request failed or got an error status back.
+    HttpError,
+    /// A response couldn't be parsed.
+    ParseError,
+    /// Facebook served a login wall, security checkpoint or consent page.
+    Blocked,
+    /// The page's output file couldn't be written.
+    WriteError,
+    /// Any other error.
+    Failed,
+    /// Not attempted, because the run was interrupted first.
+    Skipped,
+}
+
+impl TargetStatus {
+    /// The failure status for `err`, from the first cause that is a
+    /// [`FacebookError`], request, JSON or I/O error.
+    pub fn from_error(err: &anyhow::Error) -> Self {
+        for cause in err.chain() {
+            if let Some(facebook_error) = cause.downcast_ref::<FacebookError>() {
+                return if facebook_error.is_interstitial() { TargetStatus::Blocked } else { TargetStatus::HttpError };
+            }
+            if cause.is::<reqwest::Error>() {
+                return TargetStatus::HttpError;
+            }
+            if cause.is::<serde_json::Error>() {
+                return TargetStatus::ParseError;
+            }
+            if cause.is::<std::io::Error>() || cause.is::<csv::Error>() {
+                return TargetStatus::WriteError;
+            }
+        }
+        TargetStatus::Failed
+    }
+
+    pub fn is_failure(self) -> bool {
+        !matches!(self, TargetStatus::Done | TargetStatus::NoAds | TargetStatus::Skipped)
+    }
+}
+
+/// One line of a checkpoint file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointEntry {
+    pub target: String,
+    pub status: TargetStatus,
+    #[serde(default)]
+    pub page_id: Option<String>,
+    #[serde(default)]
+    pub ads: usize,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl CheckpointEntry {
+    /// A target that hasn't finished yet.
+    pub fn new(target: &str) -> Self {
+        CheckpointEntry { target: target.to_string(), status: TargetStatus::Skipped, page_id: None, ads: 0, error: None }
+    }
+}
+
+/// Progress of a multi mode run, kept in a JSON Lines file so a run that dies
+/// halfway can be picked up again.
+///
+/// Each finished target appends one [`CheckpointEntry`] and flushes it, so the
+/// file is never more than one target behind. When a target appears more than
+/// once, its last entry wins.
+pub struct Checkpoint {
+    file: File,
+    entries: HashMap<String, CheckpointEntry>,
+}
+
+impl Checkpoint {
+    /// Start a new checkpoint at `path`, discarding any previous one.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("couldn't create checkpoint {}", path.display()))?;
+        Ok(Checkpoint { file, entries: HashMap::new() })
+    }
+
+    /// Load the checkpoint at `path` and keep appending to it. A missing file
+    /// is an empty checkpoint. Lines that don't parse, such as one cut short
+    /// by a crash, are ignored.
+    pub fn resume(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut entries = HashMap::new();
+        match File::open(path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    if let Ok(entry) = serde_json::from_str::<CheckpointEntry>(&line?) {
+                        entries.insert(entry.target.clone(), entry);
+                    }
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err).with_context(|| format!("couldn't read checkpoint {}", path.display())),
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("couldn't open checkpoint {}", path.display()))?;
+        // A line cut short by a crash would otherwise swallow the next entry.
+        if file.metadata()?.len() > 0 && !ends_with_newline(path)? {
+            writeln!(file)?;
+        }
+        Ok(Checkpoint { file, entries })
+    }
+
+    /// The latest entry for `target`.
+    pub fn entry(&self, target: &str) -> Option<&CheckpointEntry> {
+        self.entries.get(target)
+    }
+
+    pub fn status(&self, target: &str) -> Option<TargetStatus> {
+        self.entry(target).map(|entry| entry.status)
+    }
+
+    /// Whether `target` finished in an earlier run and can be skipped. Failed
+    /// targets are not complete, so they are retried.
+    pub fn is_complete(&self, target: &str) -> bool {
+        matches!(self.status(target), Some(TargetStatus::Done | TargetStatus::NoAds))
+    }
+
+    /// Append `entry` and flush it to disk.
+    pub fn record(&mut self, entry: &CheckpointEntry) -> Result<()> {
+        serde_json::to_writer(&mut self.file, entry)?;
+        writeln!(self.file)?;
+        self.file.flush()?;
+        self.entries.insert(entry.target.clone(), entry.clone());
+        Ok(())
+    }
+}
+
+/// Write a summary of multi mode targets to `path`: a JSON array if it ends
+/// in `.json`, CSV otherwise.
+pub fn write_report(entries: &[CheckpointEntry], path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let mut file = BufWriter::new(File::create(path).with_context(|| format!("couldn't create report {}", path.display()))?);
+    if path.extension().is_some_and(|extension| extension == "json") {
+        serde_json::to_writer_pretty(&mut file, entries)?;
+        writeln!(file)?;
+        file.flush()?;
+    } else {
+        let mut writer = csv::Writer::from_writer(file);
+        for entry in entries {
+            writer.serialize(entry)?;
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+fn ends_with_newline(path: &Path) -> Result<bool> {
+    let content = std::fs::read(path)?;
+    Ok(content.last() == Some(&b'\n'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn entry(target: &str, status: TargetStatus) -> CheckpointEntry {
+        CheckpointEntry { status, ..CheckpointEntry::new(target) }
+    }
+
+    #[test]
+    fn test_resume_skips_finished_targets() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("multi.checkpoint.jsonl");
+
+        let mut checkpoint = Checkpoint::create(&path).unwrap();
+        checkpoint.record(&entry("a", TargetStatus::Done)).unwrap();
+        checkpoint.record(&entry("b", TargetStatus::NoAds)).unwrap();
+        checkpoint.record(&entry("c", TargetStatus::Failed)).unwrap();
+        checkpoint.record(&entry("d", TargetStatus::Failed)).unwrap();
+        checkpoint.record(&entry("d", TargetStatus::Done)).unwrap();
+        drop(checkpoint);
+
+        let checkpoint = Checkpoint::resume(&path).unwrap();
+        assert!(checkpoint.is_complete("a"));
+        assert!(checkpoint.is_complete("b"));
+        assert!(!checkpoint.is_complete("c"));
+        assert!(checkpoint.is_complete("d"));
+        assert!(!checkpoint.is_complete("e"));
+        assert_eq!(checkpoint.status("c"), Some(TargetStatus::Failed));
+    }
+
+    #[test]
+    fn test_resume_ignores_a_truncated_line() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("multi.checkpoint.jsonl");
+        std::fs::write(&path, "{\"target\":\"a\",\"status\":\"done\",\"ads\":3}\n{\"target\":\"b\",\"sta").unwrap();
+
+        let mut checkpoint = Checkpoint::resume(&path).unwrap();
+        checkpoint.record(&entry("c", TargetStatus::NoAds)).unwrap();
+        drop(checkpoint);
+
+        let checkpoint = Checkpoint::resume(&path).unwrap();
+        assert!(checkpoint.is_complete("a"));
+        assert_eq!(checkpoint.status("b"), None);
+        assert!(checkpoint.is_complete("c"));
+    }
+
+    #[test]
+    fn test_resume_without_a_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("missing.jsonl");
+
+        let checkpoint = Checkpoint::resume(&path).unwrap();
+
+        assert_eq!(checkpoint.status("a"), None);
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_status_from_error() {
+        let parse_error = serde_json::from_str::<CheckpointEntry>("{").unwrap_err();
+        let io_error = std::io::Error::new(ErrorKind::PermissionDenied, "denied");
+
+        assert_eq!(TargetStatus::from_error(&anyhow::Error::new(parse_error).context("page 2")), TargetStatus::ParseError);
+        assert_eq!(TargetStatus::from_error(&anyhow::Error::new(io_error)), TargetStatus::WriteError);
+        assert_eq!(TargetStatus::from_error(&anyhow::anyhow!("something else")), TargetStatus::Failed);
+        assert_eq!(TargetStatus::from_error(&anyhow::Error::new(FacebookError::LoginWall { url: None })), TargetStatus::Blocked);
+        assert_eq!(
+            TargetStatus::from_error(&anyhow::Error::new(FacebookError::ErrorPayload { code: Some(1), summary: None, description: None })),
+            TargetStatus::HttpError
+        );
+    }
+
+    #[test]
+    fn test_write_report_csv() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("report.csv");
+        let entries = vec![
+            CheckpointEntry { page_id: Some("1".to_string()), ads: 3, ..entry("a", TargetStatus::Done) },
+            CheckpointEntry { error: Some("HTTP status server error (500)".to_string()), ..entry("b", TargetStatus::HttpError) },
+        ];
+
+        write_report(&entries, &path).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "target,status,page_id,ads,error\na,done,1,3,\nb,http_error,,0,HTTP status server error (500)\n"
+        );
+    }
+}
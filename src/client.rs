@@ -0,0 +1,538 @@
+use anyhow::{Result, anyhow};
+use fake_useragent::UserAgents;
+use futures::{Stream, TryStreamExt, stream};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, cookie::Jar};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::session::is_error_payload;
+use crate::{
+    ADS_PAGE_SIZE, AdQuery, DEFAULT_SESSION_TTL, FacebookAd, FacebookPage, KeywordMatch, PageRef, RateLimiter, SessionTokens, build_facebook_form_data,
+    extract_session_tokens, page_id_from_html, parse_facebook_ads_payload, parse_facebook_search_response,
+};
+
+pub const DEFAULT_BASE_URL: &str = "https://www.facebook.com";
+
+/// Which `User-Agent` header requests are sent with.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum UserAgentPolicy {
+    /// A random browser user agent per request, from `fake_useragent`'s list.
+    #[default]
+    Random,
+    /// A random user agent per request, from a file with one user agent per line.
+    RandomFromFile(String),
+    /// The same user agent for every request.
+    Fixed(String),
+}
+
+/// Client for the Ad Library website.
+///
+/// Holds the HTTP client, cookie jar and user agent list so they are shared
+/// across every request made through it.
+///
+/// ```no_run
+/// # async fn run() -> anyhow::Result<()> {
+/// use facebook_totem::{AdQuery, FacebookTotem};
+///
+/// let totem = FacebookTotem::builder()
+///     .timeout(std::time::Duration::from_secs(30))
+///     .build()?;
+/// let id = totem.get_id_from_url("https://www.facebook.com/somepage").await?;
+/// let ads = totem.get_ads_from_id(&id, &AdQuery::new()).await?;
+/// for ad in &ads {
+///     println!("{:?}: {:?}", ad.ad_archive_id, ad.body);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct FacebookTotem {
+    client: Client,
+    base_url: String,
+    user_agents: Option<UserAgents>,
+    fixed_user_agent: Option<String>,
+    session: Mutex<Option<Bootstrap>>,
+    session_bootstrap: bool,
+    session_ttl: Duration,
+    rate_limiter: Option<Arc<RateLimiter>>,
+}
+
+/// The last session bootstrap. A failed one is kept too, with no tokens, so
+/// a landing page Facebook is refusing isn't asked for on every request.
+struct Bootstrap {
+    tokens: Option<SessionTokens>,
+    at: Instant,
+}
+
+pub struct FacebookTotemBuilder {
+    base_url: String,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    default_headers: Vec<(String, String)>,
+    cookie_jar: Option<Arc<Jar>>,
+    user_agent: UserAgentPolicy,
+    session_bootstrap: bool,
+    session_ttl: Duration,
+    rate_limiter: Option<Arc<RateLimiter>>,
+}
+
+impl Default for FacebookTotemBuilder {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            timeout: None,
+            connect_timeout: None,
+            default_headers: Vec::new(),
+            cookie_jar: None,
+            user_agent: UserAgentPolicy::default(),
+            session_bootstrap: true,
+            session_ttl: DEFAULT_SESSION_TTL,
+            rate_limiter: None,
+        }
+    }
+}
+
+impl FacebookTotemBuilder {
+    /// Send requests somewhere other than `https://www.facebook.com`, e.g. a mock server.
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Total time allowed for each request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Add a header sent with every request. Per-request headers of the same name win.
+    pub fn default_header(mut self, name: &str, value: &str) -> Self {
+        self.default_headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Share a cookie jar with other clients. A fresh jar is used otherwise.
+    pub fn cookie_jar(mut self, jar: Arc<Jar>) -> Self {
+        self.cookie_jar = Some(jar);
+        self
+    }
+
+    pub fn user_agent(mut self, policy: UserAgentPolicy) -> Self {
+        self.user_agent = policy;
+        self
+    }
+
+    /// Load the Ad Library landing page before the first request to pick up
+    /// session tokens (`lsd`, `jazoest`, ...). On by default.
+    pub fn session_bootstrap(mut self, enabled: bool) -> Self {
+        self.session_bootstrap = enabled;
+        self
+    }
+
+    /// How long bootstrapped session tokens are reused before being refreshed.
+    pub fn session_ttl(mut self, ttl: Duration) -> Self {
+        self.session_ttl = ttl;
+        self
+    }
+
+    /// Wait for `limiter` before every request. Clients sharing a limiter
+    /// share its budget.
+    pub fn rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    pub fn build(self) -> Result<FacebookTotem> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.default_headers {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes()).map_err(|e| anyhow!("invalid header name '{}': {}", name, e))?,
+                HeaderValue::from_str(value).map_err(|e| anyhow!("invalid value for header '{}': {}", name, e))?,
+            );
+        }
+
+        let jar = self.cookie_jar.unwrap_or_default();
+        let mut builder = Client::builder().cookie_provider(jar).default_headers(headers);
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        let (user_agents, fixed_user_agent) = match self.user_agent {
+            UserAgentPolicy::Random => (Some(UserAgents::new()), None),
+            UserAgentPolicy::RandomFromFile(path) => {
+                if !std::path::Path::new(&path).is_file() {
+                    return Err(anyhow!("user agent file not found: {}", path));
+                }
+                (Some(UserAgents::from_cache(&path)), None)
+            }
+            UserAgentPolicy::Fixed(user_agent) => (None, Some(user_agent)),
+        };
+
+        Ok(FacebookTotem {
+            client: builder.build()?,
+            base_url: self.base_url,
+            user_agents,
+            fixed_user_agent,
+            session: Mutex::new(None),
+            session_bootstrap: self.session_bootstrap,
+            session_ttl: self.session_ttl,
+            rate_limiter: self.rate_limiter,
+        })
+    }
+}
+
+impl FacebookTotem {
+    /// A client with the default settings.
+    pub fn new() -> Result<Self> {
+        Self::builder().build()
+    }
+
+    pub fn builder() -> FacebookTotemBuilder {
+        FacebookTotemBuilder::default()
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn rate_limiter(&self) -> Option<&Arc<RateLimiter>> {
+        self.rate_limiter.as_ref()
+    }
+
+    /// Wait for the rate limiter, if there is one, before sending a request.
+    async fn throttle(&self) {
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.acquire().await;
+        }
+    }
+
+    fn user_agent(&self) -> &str {
+        match (&self.fixed_user_agent, &self.user_agents) {
+            (Some(user_agent), _) => user_agent,
+            (None, Some(user_agents)) => user_agents.random(),
+            (None, None) => "",
+        }
+    }
+
+    /// Headers the Ad Library's async endpoints expect from a browser.
+    fn ad_library_headers(&self) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        headers.insert("User-Agent", self.user_agent().parse()?);
+        headers.insert("Accept", "*/*".parse()?);
+        headers.insert("Accept-Language", "en,en-US;q=0.5".parse()?);
+        headers.insert("Referer", format!("{}/ads/library/", self.base_url).parse()?);
+        headers.insert("Content-Type", "application/x-www-form-urlencoded".parse()?);
+        headers.insert("Origin", self.base_url.parse()?);
+        headers.insert("DNT", "1".parse()?);
+        headers.insert("Connection", "keep-alive".parse()?);
+        Ok(headers)
+    }
+
+    /// Current session tokens, bootstrapping them from the landing page on
+    /// first use and again once they are older than the session TTL.
+    ///
+    /// If the landing page can't be loaded or has no `lsd` token (an error
+    /// status, a login wall, ...), `None` is returned, so the request goes out
+    /// with the tokenless form as it did before bootstrapping existed. That
+    /// outcome is remembered for the session TTL as well.
+    ///
+    /// The landing page is fetched without holding the session lock, so
+    /// concurrent requests aren't queued behind a slow bootstrap.
+    pub async fn session_tokens(&self) -> Result<Option<SessionTokens>> {
+        if !self.session_bootstrap {
+            return Ok(None);
+        }
+
+        if let Some(bootstrap) = self.session.lock().await.as_ref()
+            && bootstrap.at.elapsed() < self.session_ttl
+        {
+            return Ok(bootstrap.tokens.clone());
+        }
+
+        self.throttle().await;
+        let tokens = self
+            .fetch_landing_page()
+            .await
+            .ok()
+            .map(|html| extract_session_tokens(&html))
+            .filter(|tokens| tokens.lsd.is_some());
+        *self.session.lock().await = Some(Bootstrap { tokens: tokens.clone(), at: Instant::now() });
+        Ok(tokens)
+    }
+
+    async fn fetch_landing_page(&self) -> Result<String> {
+        let response = self
+            .client
+            .get(format!("{}/ads/library/", self.base_url))
+            .header("User-Agent", self.user_agent())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.text().await?)
+    }
+
+    /// Drop the current session tokens so the next request bootstraps new ones.
+    pub async fn invalidate_session(&self) {
+        *self.session.lock().await = None;
+    }
+
+    /// POST to one of the Ad Library's async endpoints with the session's form
+    /// data. If Facebook answers a request that had session tokens with its
+    /// error payload, the session is refreshed and the request retried once.
+    async fn post_ad_library(&self, url: &str, headers: HeaderMap, params: &[(&str, &str)]) -> Result<String> {
+        let mut refreshed = false;
+        loop {
+            let session = self.session_tokens().await?;
+            let mut headers = headers.clone();
+            if let Some(lsd) = session.as_ref().and_then(|tokens| tokens.lsd.as_deref()) {
+                headers.insert("X-FB-LSD", lsd.parse()?);
+            }
+            let data = build_facebook_form_data(session.as_ref());
+
+            self.throttle().await;
+            let response = self
+                .client
+                .post(url)
+                .headers(headers)
+                .query(params)
+                .form(&data)
+                .send()
+                .await?
+                .error_for_status()?;
+            let text = response.text().await?;
+
+            if session.is_some() && !refreshed && is_error_payload(&text) {
+                self.invalidate_session().await;
+                refreshed = true;
+                continue;
+            }
+            return Ok(text);
+        }
+    }
+
+    /// The page ID for a page URL. IDs already in the URL (see [`PageRef`])
+    /// are returned without a request; otherwise the page is fetched and the
+    /// ID read from its HTML.
+    pub async fn get_id_from_url(&self, url: &str) -> Result<String> {
+        if let Some(page_id) = PageRef::parse(url).and_then(|page| page.page_id) {
+            return Ok(page_id);
+        }
+        self.throttle().await;
+        let response = self
+            .client
+            .get(url)
+            .header("User-Agent", self.user_agent())
+            .send()
+            .await?
+            .error_for_status()?;
+        let final_url = response.url().to_string();
+        let text = response.text().await?;
+
+        page_id_from_html(Some(&final_url), &text)
+    }
+
+    pub async fn get_facebook_page_from_name(&self, name: &str) -> Result<Vec<FacebookPage>> {
+        let mut headers = self.ad_library_headers()?;
+        headers.insert("TE", "Trailers".parse()?);
+
+        let params = [
+            ("ad_type", "all"),
+            ("country", ""),
+            ("is_mobile", "false"),
+            ("q", name),
+            ("session_id", "\"\""),
+        ];
+
+        let url = format!("{}/ads/library/async/search_typeahead/", self.base_url);
+        let text = self.post_ad_library(&url, headers, &params).await?;
+        parse_facebook_search_response(&text)
+    }
+
+    /// Fetch every ad for a page matching `query`, following the forward cursor
+    /// until the result set is exhausted or the query's `max_ads` is reached.
+    pub async fn get_ads_from_id(&self, id: &str, query: &AdQuery) -> Result<Vec<FacebookAd>> {
+        self.ads_stream(id, query).try_collect().await
+    }
+
+    /// Search ads across all pages by keyword, paginating like [`FacebookTotem::get_ads_from_id`].
+    pub async fn search_ads_by_keyword(&self, keywords: &str, keyword_match: KeywordMatch, query: &AdQuery) -> Result<Vec<FacebookAd>> {
+        self.keyword_ads_stream(keywords, keyword_match, query).try_collect().await
+    }
+
+    /// Ads for a page, yielded as each page of results is fetched.
+    ///
+    /// The next request is only sent once the ads already fetched have been
+    /// consumed, so dropping the stream stops the pull. The stream ends after
+    /// the first error.
+    ///
+    /// ```no_run
+    /// use facebook_totem::{AdQuery, FacebookTotem};
+    /// use futures::TryStreamExt;
+    ///
+    /// # async fn run() -> anyhow::Result<()> {
+    /// let totem = FacebookTotem::new()?;
+    /// let mut ads = std::pin::pin!(totem.ads_stream("123456789", &AdQuery::new()));
+    /// while let Some(ad) = ads.try_next().await? {
+    ///     println!("{:?}", ad.ad_archive_id);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn ads_stream<'a>(&'a self, page_id: &str, query: &AdQuery) -> impl Stream<Item = Result<FacebookAd>> + Send + 'a {
+        self.search_ads_stream(vec![("view_all_page_id", page_id.to_string())], query)
+    }
+
+    /// Ads matching `keywords` across all pages, streamed like [`FacebookTotem::ads_stream`].
+    pub fn keyword_ads_stream<'a>(
+        &'a self,
+        keywords: &str,
+        keyword_match: KeywordMatch,
+        query: &AdQuery,
+    ) -> impl Stream<Item = Result<FacebookAd>> + Send + 'a {
+        let params = vec![
+            ("q", keywords.to_string()),
+            ("search_type", keyword_match.as_search_type().to_string()),
+        ];
+        self.search_ads_stream(params, query)
+    }
+
+    /// POST to `search_ads` with `search_params` on top of the query's filters,
+    /// following the forward cursor until the results run out or `max_ads` is hit.
+    fn search_ads_stream<'a>(
+        &'a self,
+        search_params: Vec<(&'static str, String)>,
+        query: &AdQuery,
+    ) -> impl Stream<Item = Result<FacebookAd>> + Send + 'a {
+        let cursor = SearchAdsCursor {
+            search_params,
+            filter_params: query.to_search_ads_params(),
+            max_ads: query.get_max_ads(),
+            fetched: 0,
+            forward_cursor: None,
+            collation_token: None,
+            // Nothing to fetch, so don't ask for `count=0`.
+            done: query.get_max_ads() == Some(0),
+        };
+
+        stream::try_unfold(cursor, move |mut cursor| async move {
+            if cursor.done {
+                return anyhow::Ok(None);
+            }
+            let ads = self.fetch_search_ads_page(&mut cursor).await?;
+            Ok(Some((stream::iter(ads.into_iter().map(Ok)), cursor)))
+        })
+        .try_flatten()
+    }
+
+    async fn fetch_search_ads_page(&self, cursor: &mut SearchAdsCursor) -> Result<Vec<FacebookAd>> {
+        let url = format!("{}/ads/library/async/search_ads/", self.base_url);
+        let count = cursor
+            .max_ads
+            .map(|max| max.saturating_sub(cursor.fetched).min(ADS_PAGE_SIZE))
+            .unwrap_or(ADS_PAGE_SIZE)
+            .to_string();
+
+        let mut params: Vec<(&str, &str)> = vec![
+            ("session_id", "\"\""),
+            ("count", count.as_str()),
+            ("impression_search_field", "has_impressions_lifetime"),
+        ];
+        params.extend(cursor.filter_params.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        params.extend(cursor.search_params.iter().map(|(k, v)| (*k, v.as_str())));
+        if let Some(forward_cursor) = &cursor.forward_cursor {
+            params.push(("forward_cursor", forward_cursor.as_str()));
+        }
+        if let Some(token) = &cursor.collation_token {
+            params.push(("collation_token", token.as_str()));
+        }
+
+        let mut headers = self.ad_library_headers()?;
+        headers.insert("Cache-Control", "max-age=0".parse()?);
+
+        let text = self.post_ad_library(&url, headers, &params).await?;
+        let payload = parse_facebook_ads_payload(&text)?;
+        let next_cursor = payload.next_cursor().map(str::to_string);
+        if payload.collation_token.is_some() {
+            cursor.collation_token = payload.collation_token.clone();
+        }
+
+        let mut ads: Vec<FacebookAd> = payload.into_ads().into_iter().map(FacebookAd::from_value).collect();
+        if let Some(max) = cursor.max_ads {
+            ads.truncate(max.saturating_sub(cursor.fetched));
+        }
+        cursor.fetched += ads.len();
+
+        match next_cursor {
+            // A cursor that didn't move would fetch the same page forever.
+            Some(next)
+                if !ads.is_empty()
+                    && cursor.max_ads.is_none_or(|max| cursor.fetched < max)
+                    && cursor.forward_cursor.as_deref() != Some(next.as_str()) =>
+            {
+                cursor.forward_cursor = Some(next);
+            }
+            _ => cursor.done = true,
+        }
+        Ok(ads)
+    }
+}
+
+/// Where a paginated `search_ads` pull has got to.
+struct SearchAdsCursor {
+    search_params: Vec<(&'static str, String)>,
+    filter_params: Vec<(String, String)>,
+    max_ads: Option<usize>,
+    fetched: usize,
+    forward_cursor: Option<String>,
+    collation_token: Option<String>,
+    done: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_defaults_and_overrides() {
+        let totem = FacebookTotem::builder()
+            .user_agent(UserAgentPolicy::Fixed("test-agent".to_string()))
+            .build()
+            .unwrap();
+        assert_eq!(totem.base_url(), DEFAULT_BASE_URL);
+        assert_eq!(totem.user_agent(), "test-agent");
+
+        let totem = FacebookTotem::builder()
+            .base_url("http://127.0.0.1:1234/")
+            .user_agent(UserAgentPolicy::Fixed("test-agent".to_string()))
+            .build()
+            .unwrap();
+        assert_eq!(totem.base_url(), "http://127.0.0.1:1234");
+
+        let headers = totem.ad_library_headers().unwrap();
+        assert_eq!(headers["Origin"], "http://127.0.0.1:1234");
+        assert_eq!(headers["Referer"], "http://127.0.0.1:1234/ads/library/");
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_settings() {
+        let result = FacebookTotem::builder()
+            .default_header("bad header", "value")
+            .user_agent(UserAgentPolicy::Fixed("test-agent".to_string()))
+            .build();
+        assert!(result.is_err());
+
+        let result = FacebookTotem::builder()
+            .user_agent(UserAgentPolicy::RandomFromFile("/does/not/exist".to_string()))
+            .build();
+        assert!(result.is_err());
+    }
+}
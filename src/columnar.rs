@@ -0,0 +1,215 @@
+use anyhow::Result;
+use arrow::array::{ArrayRef, BooleanArray, Int64Array, ListBuilder, RecordBatch, StringArray, StringBuilder, TimestampMicrosecondArray};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::ipc::writer::FileWriter;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::fs::File;
+use std::sync::Arc;
+
+use crate::{FacebookAd, FacebookPage};
+
+/// Arrow schema for ads, from [`FacebookAd`]'s typed fields. Dates are UTC
+/// timestamps, spend and impressions bounds are integers, and the raw
+/// response object is kept as a JSON string.
+pub fn ads_schema() -> SchemaRef {
+    let text = |name: &str| Field::new(name, DataType::Utf8, true);
+    let integer = |name: &str| Field::new(name, DataType::Int64, true);
+    let timestamp = |name: &str| Field::new(name, DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())), true);
+
+    Arc::new(Schema::new(vec![
+        text("ad_archive_id"),
+        text("page_id"),
+        text("page_name"),
+        timestamp("start_date"),
+        timestamp("end_date"),
+        Field::new("is_active", DataType::Boolean, true),
+        integer("spend_lower"),
+        integer("spend_upper"),
+        integer("impressions_lower"),
+        integer("impressions_upper"),
+        text("currency"),
+        Field::new("publisher_platforms", DataType::new_list(DataType::Utf8, true), true),
+        text("body"),
+        text("title"),
+        text("link_url"),
+        text("cta_text"),
+        text("snapshot_url"),
+        Field::new("raw", DataType::Utf8, false),
+    ]))
+}
+
+/// Arrow schema for pages found by a name search.
+pub fn pages_schema() -> SchemaRef {
+    Arc::new(Schema::new(
+        ["page_id", "page_name", "page_profile_picture_uri", "page_uri"]
+            .map(|name| Field::new(name, DataType::Utf8, true))
+            .to_vec(),
+    ))
+}
+
+pub fn ads_to_record_batch(ads: &[FacebookAd]) -> Result<RecordBatch> {
+    let text = |pick: fn(&FacebookAd) -> &Option<String>| -> ArrayRef {
+        Arc::new(ads.iter().map(|ad| pick(ad).as_deref()).collect::<StringArray>())
+    };
+    let timestamp = |pick: fn(&FacebookAd) -> &Option<chrono::DateTime<chrono::Utc>>| -> ArrayRef {
+        let micros: Vec<Option<i64>> = ads.iter().map(|ad| pick(ad).map(|date| date.timestamp_micros())).collect();
+        Arc::new(TimestampMicrosecondArray::from(micros).with_timezone("UTC"))
+    };
+    let bound = |pick: fn(&FacebookAd) -> Option<u64>| -> ArrayRef {
+        Arc::new(ads.iter().map(|ad| pick(ad).and_then(|n| i64::try_from(n).ok())).collect::<Int64Array>())
+    };
+
+    let mut platforms = ListBuilder::new(StringBuilder::new());
+    for ad in ads {
+        for platform in &ad.publisher_platforms {
+            platforms.values().append_value(platform);
+        }
+        platforms.append(true);
+    }
+
+    let columns: Vec<ArrayRef> = vec![
+        text(|ad| &ad.ad_archive_id),
+        text(|ad| &ad.page_id),
+        text(|ad| &ad.page_name),
+        timestamp(|ad| &ad.start_date),
+        timestamp(|ad| &ad.end_date),
+        Arc::new(ads.iter().map(|ad| ad.is_active).collect::<BooleanArray>()),
+        bound(|ad| ad.spend.as_ref()?.lower),
+        bound(|ad| ad.spend.as_ref()?.upper),
+        bound(|ad| ad.impressions.as_ref()?.lower),
+        bound(|ad| ad.impressions.as_ref()?.upper),
+        text(|ad| &ad.currency),
+        Arc::new(platforms.finish()),
+        text(|ad| &ad.body),
+        text(|ad| &ad.title),
+        text(|ad| &ad.link_url),
+        text(|ad| &ad.cta_text),
+        text(|ad| &ad.snapshot_url),
+        Arc::new(ads.iter().map(|ad| Some(ad.raw.to_string())).collect::<StringArray>()),
+    ];
+    Ok(RecordBatch::try_new(ads_schema(), columns)?)
+}
+
+pub fn pages_to_record_batch(pages: &[FacebookPage]) -> Result<RecordBatch> {
+    let text = |pick: fn(&FacebookPage) -> &Option<String>| -> ArrayRef {
+        Arc::new(pages.iter().map(|page| pick(page).as_deref()).collect::<StringArray>())
+    };
+    let columns = vec![
+        text(|page| &page.page_id),
+        text(|page| &page.page_name),
+        text(|page| &page.page_profile_picture_uri),
+        text(|page| &page.page_uri),
+    ];
+    Ok(RecordBatch::try_new(pages_schema(), columns)?)
+}
+
+pub fn write_ads_to_parquet(ads: &[FacebookAd], filename: &str) -> Result<()> {
+    write_parquet(&ads_to_record_batch(ads)?, filename)
+}
+
+pub fn write_facebook_pages_to_parquet(pages: &[FacebookPage], filename: &str) -> Result<()> {
+    write_parquet(&pages_to_record_batch(pages)?, filename)
+}
+
+/// Write ads to an Arrow IPC (Feather v2) file.
+pub fn write_ads_to_arrow(ads: &[FacebookAd], filename: &str) -> Result<()> {
+    write_arrow_ipc(&ads_to_record_batch(ads)?, filename)
+}
+
+/// Write pages to an Arrow IPC (Feather v2) file.
+pub fn write_facebook_pages_to_arrow(pages: &[FacebookPage], filename: &str) -> Result<()> {
+    write_arrow_ipc(&pages_to_record_batch(pages)?, filename)
+}
+
+fn write_parquet(batch: &RecordBatch, filename: &str) -> Result<()> {
+    let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+    let mut writer = ArrowWriter::try_new(File::create(filename)?, batch.schema(), Some(properties))?;
+    writer.write(batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+fn write_arrow_ipc(batch: &RecordBatch, filename: &str) -> Result<()> {
+    let mut writer = FileWriter::try_new(File::create(filename)?, &batch.schema())?;
+    writer.write(batch)?;
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, AsArray};
+    use arrow::datatypes::{Int64Type, TimestampMicrosecondType};
+    use arrow::ipc::reader::FileReader;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use serde_json::json;
+
+    fn ads() -> Vec<FacebookAd> {
+        vec![
+            json!({
+                "adArchiveID": "1",
+                "pageID": "10",
+                "startDate": 1700000000,
+                "isActive": true,
+                "spend": "<100",
+                "impressionsWithIndex": {"impressionsText": "1K-5K"},
+                "publisherPlatform": ["FACEBOOK", "INSTAGRAM"]
+            })
+            .into(),
+            json!({"adArchiveID": "2"}).into(),
+        ]
+    }
+
+    #[test]
+    fn test_ads_to_record_batch() {
+        let batch = ads_to_record_batch(&ads()).unwrap();
+
+        assert_eq!(batch.num_rows(), 2);
+        let start_date = batch.column_by_name("start_date").unwrap().as_primitive::<TimestampMicrosecondType>();
+        assert_eq!(start_date.value(0), 1_700_000_000_000_000);
+        assert!(start_date.is_null(1));
+        let spend_upper = batch.column_by_name("spend_upper").unwrap().as_primitive::<Int64Type>();
+        assert_eq!(spend_upper.value(0), 99);
+        let impressions_lower = batch.column_by_name("impressions_lower").unwrap().as_primitive::<Int64Type>();
+        assert_eq!(impressions_lower.value(0), 1_000);
+        let platforms = batch.column_by_name("publisher_platforms").unwrap().as_list::<i32>();
+        assert_eq!(platforms.value(0).as_string::<i32>().value(1), "instagram");
+        assert_eq!(platforms.value(1).len(), 0);
+        assert_eq!(batch.column_by_name("raw").unwrap().as_string::<i32>().value(1), r#"{"adArchiveID":"2"}"#);
+    }
+
+    #[test]
+    fn test_write_ads_to_parquet_round_trip() {
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let temp_path = temp_file.path().to_str().unwrap();
+
+        write_ads_to_parquet(&ads(), temp_path).unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(temp_path).unwrap()).unwrap().build().unwrap();
+        let batches: Vec<RecordBatch> = reader.collect::<std::result::Result<_, _>>().unwrap();
+        assert_eq!(batches[0].schema(), ads_schema());
+        assert_eq!(batches[0], ads_to_record_batch(&ads()).unwrap());
+    }
+
+    #[test]
+    fn test_write_pages_to_arrow_round_trip() {
+        let pages = vec![FacebookPage {
+            page_id: Some("123".to_string()),
+            page_name: Some("Test Page".to_string()),
+            page_profile_picture_uri: None,
+            page_uri: Some("test".to_string()),
+        }];
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let temp_path = temp_file.path().to_str().unwrap();
+
+        write_facebook_pages_to_arrow(&pages, temp_path).unwrap();
+
+        let mut reader = FileReader::try_new(File::open(temp_path).unwrap(), None).unwrap();
+        let batch = reader.next().unwrap().unwrap();
+        assert_eq!(batch, pages_to_record_batch(&pages).unwrap());
+        assert_eq!(batch.column_by_name("page_name").unwrap().as_string::<i32>().value(0), "Test Page");
+    }
+}
@@ -0,0 +1,186 @@
+use serde_json::Value;
+use std::fmt;
+
+/// First path segments of pages Facebook redirects to instead of the one asked for.
+const LOGIN_PATHS: &[&str] = &["/login", "/login.php"];
+const CHECKPOINT_PATHS: &[&str] = &["/checkpoint"];
+const CONSENT_PATHS: &[&str] = &["/privacy/consent", "/consent"];
+
+/// Markers in the HTML of each kind of interstitial page.
+const LOGIN_MARKERS: &[&str] = &["id=\"login_form\"", "/login/device-based/", "<title>Log in to Facebook", "<title>Log into Facebook"];
+const CHECKPOINT_MARKERS: &[&str] = &["action=\"/checkpoint/", "/checkpoint/block/", "checkpointSubmitButton"];
+const CONSENT_MARKERS: &[&str] = &["data-cookiebanner=", "/privacy/consent/", "cookie_consent_dialog"];
+
+/// Responses Facebook sends instead of the page or results that were asked
+/// for. They come back with a success status, so without these they surface
+/// as a missing page ID or a JSON error.
+///
+/// The client returns them inside its [`anyhow::Error`]s; find them with
+/// `err.downcast_ref::<FacebookError>()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FacebookError {
+    /// A login form was served instead of the page. Facebook does this to
+    /// clients it thinks are sending too many requests.
+    LoginWall { url: Option<String> },
+    /// A security checkpoint was served, which has to be passed in a browser.
+    Checkpoint { url: Option<String> },
+    /// A cookie consent page was served, as happens for some regions.
+    ConsentWall { url: Option<String> },
+    /// A `for (;;);` JSON response carrying an `error` instead of a `payload`.
+    ErrorPayload { code: Option<i64>, summary: Option<String>, description: Option<String> },
+}
+
+impl FacebookError {
+    /// Whether the request was blocked by an interstitial page rather than
+    /// answered with an error.
+    pub fn is_interstitial(&self) -> bool {
+        !matches!(self, FacebookError::ErrorPayload { .. })
+    }
+}
+
+impl fmt::Display for FacebookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let instead_of = |url: &Option<String>| url.as_deref().map_or("the response".to_string(), |url| url.to_string());
+        match self {
+            FacebookError::LoginWall { url } => write!(
+                f,
+                "Facebook showed a login wall instead of {}. It does this when too many requests come from one address; wait a while and send fewer requests per second",
+                instead_of(url)
+            ),
+            FacebookError::Checkpoint { url } => write!(
+                f,
+                "Facebook showed a security checkpoint instead of {}. Open Facebook in a browser on the same network, complete the check, then try again",
+                instead_of(url)
+            ),
+            FacebookError::ConsentWall { url } => write!(
+                f,
+                "Facebook showed a cookie consent page instead of {}. Accept it in a browser and share that browser's cookies with the client, or connect from another region",
+                instead_of(url)
+            ),
+            FacebookError::ErrorPayload { code, summary, description } => {
+                write!(f, "Facebook returned an error")?;
+                if let Some(code) = code {
+                    write!(f, " {}", code)?;
+                }
+                if let Some(summary) = summary {
+                    write!(f, ": {}", summary)?;
+                }
+                if let Some(description) = description {
+                    write!(f, " ({})", description)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for FacebookError {}
+
+/// The interstitial page `html` is, if it is one. `url` is where the request
+/// ended up after redirects; being sent to the login, checkpoint or consent
+/// pages is enough on its own.
+///
+/// Public pages include a login form for logged-out visitors too, so only
+/// call this on a page that was missing what was expected of it.
+///
+/// ```
+/// use facebook_totem::{detect_interstitial, FacebookError};
+///
+/// let error = detect_interstitial(Some("https://www.facebook.com/login/?next=%2Fsomepage"), "<html></html>");
+/// assert!(matches!(error, Some(FacebookError::LoginWall { .. })));
+/// ```
+pub fn detect_interstitial(url: Option<&str>, html: &str) -> Option<FacebookError> {
+    let path = url.map(url_path).unwrap_or_default();
+    let url = url.map(str::to_string);
+    let matches = |paths: &[&str], markers: &[&str]| {
+        paths.iter().any(|prefix| path == *prefix || path.starts_with(&format!("{}/", prefix)))
+            || markers.iter().any(|marker| html.contains(marker))
+    };
+
+    if matches(CHECKPOINT_PATHS, CHECKPOINT_MARKERS) {
+        Some(FacebookError::Checkpoint { url })
+    } else if matches(CONSENT_PATHS, CONSENT_MARKERS) {
+        Some(FacebookError::ConsentWall { url })
+    } else if matches(LOGIN_PATHS, LOGIN_MARKERS) {
+        Some(FacebookError::LoginWall { url })
+    } else {
+        None
+    }
+}
+
+/// The error in a `for (;;);`-prefixed response, if it has one.
+pub(crate) fn error_payload(text: &str) -> Option<FacebookError> {
+    let cleaned_text = text.replace("for (;;);", "");
+    let value: Value = serde_json::from_str(&cleaned_text).ok()?;
+    let error = value.get("error")?;
+    let text_field = |key: &str| value.get(key).and_then(Value::as_str).filter(|text| !text.is_empty()).map(str::to_string);
+    Some(FacebookError::ErrorPayload {
+        code: error.as_i64(),
+        summary: text_field("errorSummary"),
+        description: text_field("errorDescription"),
+    })
+}
+
+/// Explain why an async endpoint's response didn't parse: an error payload or
+/// an interstitial page if it is one, or `err` itself.
+pub(crate) fn explain_parse_error(text: &str, err: serde_json::Error) -> anyhow::Error {
+    match error_payload(text).or_else(|| detect_interstitial(None, text)) {
+        Some(facebook_error) => facebook_error.into(),
+        None => err.into(),
+    }
+}
+
+/// The path of `url`, without the query or fragment.
+fn url_path(url: &str) -> &str {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let path = without_scheme.find('/').map_or("", |start| &without_scheme[start..]);
+    path.split(['?', '#']).next().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_interstitial_from_redirect() {
+        let detect = |url: &str| detect_interstitial(Some(url), "<html></html>");
+
+        assert_eq!(detect("https://www.facebook.com/login/?next=%2Fsomepage"), Some(FacebookError::LoginWall {
+            url: Some("https://www.facebook.com/login/?next=%2Fsomepage".to_string())
+        }));
+        assert!(matches!(detect("https://m.facebook.com/login.php"), Some(FacebookError::LoginWall { .. })));
+        assert!(matches!(detect("https://www.facebook.com/checkpoint/828281030927956/"), Some(FacebookError::Checkpoint { .. })));
+        assert!(matches!(detect("https://www.facebook.com/privacy/consent/user_cookie_choice/"), Some(FacebookError::ConsentWall { .. })));
+        assert_eq!(detect("https://www.facebook.com/loginpage"), None);
+        assert_eq!(detect("https://www.facebook.com/somepage/?ref=login"), None);
+    }
+
+    #[test]
+    fn test_detect_interstitial_from_html() {
+        let login = r#"<html><head><title>Log into Facebook</title></head><form id="login_form" action="/login/device-based/regular/login/"></form></html>"#;
+        let checkpoint = r#"<html><form method="post" action="/checkpoint/?next"><button id="checkpointSubmitButton"></button></form></html>"#;
+        let consent = r#"<html><div data-cookiebanner="banner"><button data-cookiebanner="accept_button">Allow all cookies</button></div></html>"#;
+
+        assert_eq!(detect_interstitial(None, login), Some(FacebookError::LoginWall { url: None }));
+        assert_eq!(detect_interstitial(None, checkpoint), Some(FacebookError::Checkpoint { url: None }));
+        assert_eq!(detect_interstitial(None, consent), Some(FacebookError::ConsentWall { url: None }));
+        assert_eq!(detect_interstitial(None, "<html>some page</html>"), None);
+    }
+
+    #[test]
+    fn test_error_payload() {
+        let error = error_payload(r#"for (;;);{"__ar":1,"error":1357004,"errorSummary":"Sorry, something went wrong","errorDescription":"Please try closing and re-opening your browser window."}"#);
+
+        assert_eq!(error, Some(FacebookError::ErrorPayload {
+            code: Some(1357004),
+            summary: Some("Sorry, something went wrong".to_string()),
+            description: Some("Please try closing and re-opening your browser window.".to_string()),
+        }));
+        assert_eq!(
+            error.unwrap().to_string(),
+            "Facebook returned an error 1357004: Sorry, something went wrong (Please try closing and re-opening your browser window.)"
+        );
+        assert_eq!(error_payload(r#"for (;;);{"payload":{"results":[]}}"#), None);
+        assert_eq!(error_payload("<html></html>"), None);
+    }
+}
@@ -0,0 +1,230 @@
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+use std::str::FromStr;
+
+use crate::ad::{RANGE_COLUMNS, has_range_fields, range_column_values};
+
+/// An output column picked out of each row by a path expression.
+///
+/// Paths are dot-separated keys, with numeric segments indexing into arrays
+/// (`snapshot.cards.0.title`). `path:name` renames the column; otherwise the
+/// path itself is the column name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub path: String,
+    pub name: String,
+}
+
+impl FromStr for Field {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (path, name) = match s.split_once(':') {
+            Some((path, name)) => (path.trim(), name.trim()),
+            None => (s.trim(), s.trim()),
+        };
+        if path.is_empty() || name.is_empty() {
+            return Err(anyhow!("invalid field '{}', expected 'path' or 'path:name'", s));
+        }
+        Ok(Field {
+            path: path.to_string(),
+            name: name.to_string(),
+        })
+    }
+}
+
+/// Parse a comma-separated list of fields, e.g. `adArchiveID,snapshot.title:title`.
+pub fn parse_fields(spec: &str) -> Result<Vec<Field>> {
+    spec.split(',')
+        .filter(|field| !field.trim().is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// Look up a dotted path in a value. A key equal to the whole path wins, so
+/// paths also work on rows that have already been flattened.
+pub fn lookup_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    if let Some(found) = value.get(path) {
+        return Some(found);
+    }
+    path.split('.').try_fold(value, |current, segment| match current {
+        Value::Array(items) => items.get(segment.parse::<usize>().ok()?),
+        _ => current.get(segment),
+    })
+}
+
+/// Pick `fields` out of every row, in order. Missing values are `Null`.
+///
+/// Fails if any field matches no row at all, which usually means a typo in the path.
+pub fn project_rows(data: &[Value], fields: &[Field]) -> Result<Vec<Vec<Value>>> {
+    let mut projection = Projection::new(fields.to_vec());
+    let rows = data.iter().map(|row| projection.project(row)).collect();
+    projection.check()?;
+    Ok(rows)
+}
+
+/// Projects rows one at a time, remembering which fields ever matched so a
+/// streaming writer can still report bad paths at the end.
+#[derive(Debug, Clone)]
+pub(crate) struct Projection {
+    fields: Vec<Field>,
+    matched: Vec<bool>,
+    rows_seen: bool,
+}
+
+impl Projection {
+    pub(crate) fn new(fields: Vec<Field>) -> Self {
+        let matched = vec![false; fields.len()];
+        Projection { fields, matched, rows_seen: false }
+    }
+
+    pub(crate) fn names(&self) -> impl Iterator<Item = &str> {
+        self.fields.iter().map(|field| field.name.as_str())
+    }
+
+    pub(crate) fn project(&mut self, row: &Value) -> Vec<Value> {
+        self.rows_seen = true;
+        let ranges = has_range_fields(row).then(|| range_column_values(row));
+        self.fields
+            .iter()
+            .zip(&mut self.matched)
+            .map(|(field, matched)| {
+                let value = lookup_path(row, &field.path).cloned().or_else(|| {
+                    let column = RANGE_COLUMNS.iter().position(|c| *c == field.path)?;
+                    Some(Value::String(ranges.as_ref()?[column].clone()))
+                });
+                *matched |= value.is_some();
+                value.unwrap_or(Value::Null)
+            })
+            .collect()
+    }
+
+    /// [`Projection::project`] as an object keyed by the column names.
+    pub(crate) fn project_object(&mut self, row: &Value) -> Value {
+        let cells = self.project(row);
+        Value::Object(self.names().map(str::to_string).zip(cells).collect())
+    }
+
+    /// Fail if a field asks the first record's rows for a key they don't
+    /// have, which is almost always a typo. Run before anything is written,
+    /// so the mistake doesn't leave a full file behind; values that are
+    /// null, empty or short in the first rows are left to
+    /// [`Projection::check`] at the end.
+    pub(crate) fn check_first(&self, rows: &[Value]) -> Result<()> {
+        let unknown: Vec<&str> = self
+            .fields
+            .iter()
+            .filter(|field| !rows.iter().any(|row| may_be_in(row, &field.path)))
+            .map(|field| field.path.as_str())
+            .collect();
+        if !unknown.is_empty() {
+            return Err(anyhow!("field paths matched nothing in the first row: {}", unknown.join(", ")));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check(&self) -> Result<()> {
+        let unmatched: Vec<&str> = self
+            .fields
+            .iter()
+            .zip(&self.matched)
+            .filter(|(_, matched)| !**matched)
+            .map(|(field, _)| field.path.as_str())
+            .collect();
+        if self.rows_seen && !unmatched.is_empty() {
+            return Err(anyhow!("field paths matched no rows: {}", unmatched.join(", ")));
+        }
+        Ok(())
+    }
+}
+
+/// Whether `path` could be in rows like `row`: it is there, or it runs into
+/// a null, empty or array value that other rows may fill in. A path that
+/// asks an object for a key it doesn't have can't be.
+fn may_be_in(row: &Value, path: &str) -> bool {
+    if lookup_path(row, path).is_some() || (RANGE_COLUMNS.contains(&path) && has_range_fields(row)) {
+        return true;
+    }
+    let segments: Vec<&str> = path.split('.').collect();
+    for depth in (1..segments.len()).rev() {
+        let prefix = segments[..depth].join(".");
+        match lookup_path(row, &prefix) {
+            Some(Value::Object(object)) => return object.is_empty(),
+            Some(_) => return true,
+            // Flattened rows have `prefix.key` columns instead of an object.
+            None if row.as_object().is_some_and(|object| object.keys().any(|key| key.starts_with(&format!("{}.", prefix)))) => {
+                return segments[depth].parse::<usize>().is_ok();
+            }
+            None => {}
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_fields() {
+        let fields = parse_fields("adArchiveID, snapshot.title:title,").unwrap();
+
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[0], Field { path: "adArchiveID".to_string(), name: "adArchiveID".to_string() });
+        assert_eq!(fields[1], Field { path: "snapshot.title".to_string(), name: "title".to_string() });
+        assert!(parse_fields("a,:b").is_err());
+    }
+
+    #[test]
+    fn test_lookup_path() {
+        let value = json!({"snapshot": {"cards": [{"title": "A"}]}, "flat.key": 1});
+
+        assert_eq!(lookup_path(&value, "snapshot.cards.0.title"), Some(&json!("A")));
+        assert_eq!(lookup_path(&value, "flat.key"), Some(&json!(1)));
+        assert_eq!(lookup_path(&value, "snapshot.cards.1.title"), None);
+        assert_eq!(lookup_path(&value, "snapshot.cards.x"), None);
+    }
+
+    #[test]
+    fn test_project_rows() {
+        let data = vec![
+            json!({"adArchiveID": "1", "spend": {"lower_bound": "100", "upper_bound": "199"}}),
+            json!({"adArchiveID": "2", "snapshot": {"title": "T"}}),
+        ];
+        let fields = parse_fields("snapshot.title:title,adArchiveID,spend.lower_bound,spend_upper").unwrap();
+        let rows = project_rows(&data, &fields).unwrap();
+
+        assert_eq!(rows[0], vec![Value::Null, json!("1"), json!("100"), json!("199")]);
+        assert_eq!(rows[1], vec![json!("T"), json!("2"), Value::Null, Value::Null]);
+    }
+
+    #[test]
+    fn test_project_rows_unmatched_path() {
+        let data = vec![json!({"adArchiveID": "1"})];
+        let err = project_rows(&data, &parse_fields("adArchiveID,adArchiveId").unwrap()).unwrap_err();
+
+        assert!(err.to_string().contains("adArchiveId"));
+    }
+
+    #[test]
+    fn test_check_first_row() {
+        let rows = [json!({
+            "adArchiveID": "1",
+            "snapshot": {"title": null, "body": null, "cards": [], "extra": {}},
+            "spend": {"lower_bound": "1", "upper_bound": "9"},
+        })];
+        let check = |spec: &str| Projection::new(parse_fields(spec).unwrap()).check_first(&rows);
+
+        assert!(check("adArchiveID,snapshot.title,snapshot.cards.0.title,spend_upper").is_ok());
+        assert!(check("snapshot.body.text,snapshot.extra.x").is_ok());
+        assert!(check("adArchiveId").unwrap_err().to_string().contains("adArchiveId"));
+        assert!(check("snapshots.title").is_err());
+        assert!(check("snapshot.titel").is_err());
+
+        let flattened = [json!({"snapshot.title": "T", "snapshot.cards.0.title": "A"})];
+        let check = |spec: &str| Projection::new(parse_fields(spec).unwrap()).check_first(&flattened);
+        assert!(check("snapshot.title,snapshot.cards.1.title").is_ok());
+        assert!(check("snapshot.titel").is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use anyhow::{Result, anyhow};
+use serde_json::{Map, Value};
+
+/// How arrays are handled when flattening.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArrayMode {
+    /// One column per element: `snapshot.cards.0.title`, `snapshot.cards.1.title`, ...
+    #[default]
+    Index,
+    /// The whole array in one cell, elements separated by the join separator.
+    Join,
+    /// One row per element. Several arrays in the same object multiply out,
+    /// up to [`FlattenOptions::max_rows`].
+    Explode,
+}
+
+/// Options for expanding nested JSON into dotted columns.
+///
+/// ```
+/// use facebook_totem::{flatten_value, ArrayMode, FlattenOptions};
+/// use serde_json::json;
+///
+/// let ad = json!({"snapshot": {"body": {"text": "Hi"}, "cards": [{"title": "A"}]}});
+/// let rows = flatten_value(&ad, &FlattenOptions::new())?;
+/// assert_eq!(rows[0]["snapshot.body.text"], "Hi");
+/// assert_eq!(rows[0]["snapshot.cards.0.title"], "A");
+///
+/// let rows = flatten_value(&ad, &FlattenOptions::new().max_depth(2).arrays(ArrayMode::Join))?;
+/// assert_eq!(rows[0]["snapshot.body"], json!({"text": "Hi"}));
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlattenOptions {
+    max_depth: Option<usize>,
+    arrays: ArrayMode,
+    separator: String,
+    max_rows: usize,
+}
+
+/// Rows one value may explode into before flattening fails.
+pub const DEFAULT_MAX_EXPLODED_ROWS: usize = 1000;
+
+impl Default for FlattenOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: None,
+            arrays: ArrayMode::default(),
+            separator: "; ".to_string(),
+            max_rows: DEFAULT_MAX_EXPLODED_ROWS,
+        }
+    }
+}
+
+impl FlattenOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximum number of path segments in a column name. Anything nested
+    /// deeper stays a JSON value in the last column. `1` keeps only top-level
+    /// keys, as the unflattened output does, and `0` is taken as `1`.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth.max(1));
+        self
+    }
+
+    pub fn arrays(mut self, arrays: ArrayMode) -> Self {
+        self.arrays = arrays;
+        self
+    }
+
+    /// Separator between elements in [`ArrayMode::Join`]. Defaults to `"; "`.
+    pub fn separator(mut self, separator: &str) -> Self {
+        self.separator = separator.to_string();
+        self
+    }
+
+    /// Most rows one value may become in [`ArrayMode::Explode`]; more is an
+    /// error. Defaults to [`DEFAULT_MAX_EXPLODED_ROWS`].
+    pub fn max_rows(mut self, max_rows: usize) -> Self {
+        self.max_rows = max_rows;
+        self
+    }
+}
+
+/// Column a non-object value ends up in when it is flattened on its own.
+pub(crate) const NON_OBJECT_COLUMN: &str = "value";
+
+/// Flatten one JSON value into rows of dotted-path columns. Every value is
+/// one row except in [`ArrayMode::Explode`], where arrays add rows, and
+/// more than [`FlattenOptions::max_rows`] of them is an error.
+pub fn flatten_value(value: &Value, options: &FlattenOptions) -> Result<Vec<Map<String, Value>>> {
+    let mut rows = vec![Map::new()];
+    flatten_into(&mut rows, None, value, 0, options)?;
+    Ok(rows)
+}
+
+fn flatten_into(
+    rows: &mut Vec<Map<String, Value>>,
+    prefix: Option<&str>,
+    value: &Value,
+    depth: usize,
+    options: &FlattenOptions,
+) -> Result<()> {
+    let can_descend = options.max_depth.is_none_or(|max| depth < max);
+    let child = |key: &str| match prefix {
+        Some(prefix) => format!("{}.{}", prefix, key),
+        None => key.to_string(),
+    };
+
+    match value {
+        Value::Object(obj) if can_descend && !obj.is_empty() => {
+            for (key, item) in obj {
+                flatten_into(rows, Some(&child(key)), item, depth + 1, options)?;
+            }
+        }
+        Value::Array(items) if can_descend && !items.is_empty() => match options.arrays {
+            ArrayMode::Index => {
+                for (i, item) in items.iter().enumerate() {
+                    flatten_into(rows, Some(&child(&i.to_string())), item, depth + 1, options)?;
+                }
+            }
+            ArrayMode::Join => {
+                let joined = items
+                    .iter()
+                    .map(|item| match item {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(&options.separator);
+                insert_all(rows, prefix, Value::String(joined));
+            }
+            ArrayMode::Explode => {
+                let mut exploded = Vec::with_capacity(rows.len() * items.len());
+                for row in rows.drain(..) {
+                    for item in items {
+                        let mut branch = vec![row.clone()];
+                        // The element takes the array's place, so it doesn't count as a level.
+                        flatten_into(&mut branch, prefix, item, depth, options)?;
+                        exploded.extend(branch);
+                        if exploded.len() > options.max_rows {
+                            return Err(anyhow!(
+                                "exploding arrays makes more than {} rows out of one value; use index or join arrays instead",
+                                options.max_rows
+                            ));
+                        }
+                    }
+                }
+                *rows = exploded;
+            }
+        },
+        _ => insert_all(rows, prefix, value.clone()),
+    }
+    Ok(())
+}
+
+fn insert_all(rows: &mut [Map<String, Value>], key: Option<&str>, value: Value) {
+    let key = key.unwrap_or(NON_OBJECT_COLUMN);
+    for row in rows {
+        row.insert(key.to_string(), value.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn ad() -> Value {
+        json!({
+            "adArchiveID": "1",
+            "publisherPlatform": ["FACEBOOK", "INSTAGRAM"],
+            "snapshot": {
+                "body": {"text": "Buy now"},
+                "cards": [{"title": "A"}, {"title": "B"}]
+            }
+        })
+    }
+
+    #[test]
+    fn test_flatten_index_mode() {
+        let rows = flatten_value(&ad(), &FlattenOptions::new()).unwrap();
+
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["adArchiveID"], "1");
+        assert_eq!(rows[0]["publisherPlatform.1"], "INSTAGRAM");
+        assert_eq!(rows[0]["snapshot.body.text"], "Buy now");
+        assert_eq!(rows[0]["snapshot.cards.0.title"], "A");
+        assert_eq!(rows[0]["snapshot.cards.1.title"], "B");
+    }
+
+    #[test]
+    fn test_flatten_max_depth() {
+        let rows = flatten_value(&ad(), &FlattenOptions::new().max_depth(2)).unwrap();
+
+        assert_eq!(rows[0]["snapshot.body"], json!({"text": "Buy now"}));
+        assert_eq!(rows[0]["publisherPlatform.0"], "FACEBOOK");
+
+        let rows = flatten_value(&ad(), &FlattenOptions::new().max_depth(1)).unwrap();
+        assert_eq!(rows[0]["snapshot"], ad()["snapshot"]);
+    }
+
+    #[test]
+    fn test_flatten_join_mode() {
+        let options = FlattenOptions::new().arrays(ArrayMode::Join).separator("|");
+        let rows = flatten_value(&ad(), &options).unwrap();
+
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["publisherPlatform"], "FACEBOOK|INSTAGRAM");
+        assert_eq!(rows[0]["snapshot.cards"], r#"{"title":"A"}|{"title":"B"}"#);
+    }
+
+    #[test]
+    fn test_flatten_explode_mode() {
+        let rows = flatten_value(&ad(), &FlattenOptions::new().arrays(ArrayMode::Explode)).unwrap();
+
+        assert_eq!(rows.len(), 4);
+        assert!(rows.iter().all(|row| row["adArchiveID"] == "1"));
+        assert_eq!(rows[0]["publisherPlatform"], "FACEBOOK");
+        assert_eq!(rows[0]["snapshot.cards.title"], "A");
+        assert_eq!(rows[1]["snapshot.cards.title"], "B");
+        assert_eq!(rows[3]["publisherPlatform"], "INSTAGRAM");
+    }
+
+    #[test]
+    fn test_flatten_scalars_and_empty_containers() {
+        let rows = flatten_value(&json!("loose"), &FlattenOptions::new()).unwrap();
+        assert_eq!(rows[0][NON_OBJECT_COLUMN], "loose");
+
+        let rows = flatten_value(&json!({"cards": [], "extra": {}}), &FlattenOptions::new().arrays(ArrayMode::Explode)).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["cards"], json!([]));
+        assert_eq!(rows[0]["extra"], json!({}));
+    }
+
+    #[test]
+    fn test_flatten_explode_row_cap() {
+        let options = FlattenOptions::new().arrays(ArrayMode::Explode).max_rows(3);
+
+        assert!(flatten_value(&ad(), &options).unwrap_err().to_string().contains("more than 3 rows"));
+        assert_eq!(flatten_value(&ad(), &options.max_rows(4)).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_flatten_zero_depth_is_one() {
+        let rows = flatten_value(&ad(), &FlattenOptions::new().max_depth(0)).unwrap();
+        assert_eq!(rows[0]["snapshot"], ad()["snapshot"]);
+    }
+}
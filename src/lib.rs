@@ -1,12 +1,58 @@
 use anyhow::Result;
-use fake_useragent::UserAgents;
-use reqwest::{Client, cookie::Jar};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
-use std::fs::File;
-use std::sync::Arc;
-use csv::Writer;
+use std::io::Write;
+
+mod ad;
+mod backend;
+mod checkpoint;
+mod client;
+#[cfg(feature = "parquet")]
+mod columnar;
+mod error;
+mod fields;
+mod flatten;
+mod page_id;
+mod page_ref;
+mod query;
+mod range;
+mod rate_limit;
+mod session;
+mod sink;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod targets;
+mod template;
+
+use ad::{has_range_fields, range_column_values, RANGE_COLUMNS};
+use error::explain_parse_error;
+
+pub use ad::FacebookAd;
+pub use backend::{AdLibraryBackend, GraphApiBackend, ScraperBackend, GRAPH_API_DEFAULT_FIELDS};
+pub use checkpoint::{write_report, Checkpoint, CheckpointEntry, TargetStatus};
+pub use client::{FacebookTotem, FacebookTotemBuilder, UserAgentPolicy, DEFAULT_BASE_URL};
+#[cfg(feature = "parquet")]
+pub use columnar::{
+    ads_schema, ads_to_record_batch, pages_schema, pages_to_record_batch, write_ads_to_arrow, write_ads_to_parquet,
+    write_facebook_pages_to_arrow, write_facebook_pages_to_parquet,
+};
+pub use error::{detect_interstitial, FacebookError};
+pub use fields::{lookup_path, parse_fields, project_rows, Field};
+pub use flatten::{flatten_value, ArrayMode, FlattenOptions, DEFAULT_MAX_EXPLODED_ROWS};
+pub use page_id::{page_id_candidates, PageIdCandidate, PageIdSource};
+pub use page_ref::PageRef;
+pub use query::{ActiveStatus, AdQuery, AdType, MediaType, PublisherPlatform, SortDirection, SortMode};
+pub use range::Range;
+pub use rate_limit::RateLimiter;
+pub use session::{extract_session_tokens, SessionTokens, DEFAULT_SESSION_TTL};
+pub use sink::{
+    write_ads_to_sink, write_facebook_pages_to_sink, write_json_to_sink, CsvSink, JsonSink, JsonlSink, LazyFile, OutputSink,
+};
+#[cfg(feature = "sqlite")]
+pub use sqlite::{write_ads_to_sqlite, write_facebook_pages_to_sqlite, SqliteStore};
+pub use targets::{parse_targets, read_targets, InputFormat, Target};
+pub use template::{FilenameTemplate, FilenameValues};
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct FacebookPage {
@@ -39,202 +85,328 @@ pub struct FacebookAdsResponse {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FacebookAdsPayload {
     pub results: Vec<Vec<Value>>,
+    #[serde(rename = "forwardCursor", default)]
+    pub forward_cursor: Option<String>,
+    #[serde(rename = "backwardCursor", default)]
+    pub backward_cursor: Option<String>,
+    #[serde(rename = "collationToken", default)]
+    pub collation_token: Option<String>,
+    #[serde(rename = "isResultComplete", default)]
+    pub is_result_complete: bool,
+    #[serde(rename = "totalCount", default)]
+    pub total_count: Option<u64>,
+}
+
+impl FacebookAdsPayload {
+    /// Cursor to request the next page with, if there is one.
+    pub fn next_cursor(&self) -> Option<&str> {
+        if self.is_result_complete {
+            return None;
+        }
+        self.forward_cursor.as_deref().filter(|cursor| !cursor.is_empty())
+    }
+
+    pub fn into_ads(self) -> Vec<Value> {
+        self.results.into_iter().flatten().collect()
+    }
 }
 
+/// Number of ads requested per `search_ads` call.
+pub(crate) const ADS_PAGE_SIZE: usize = 30;
+
 pub async fn get_id_from_url(url: &str) -> Result<String> {
-    let client = Client::new();
-    let response = client.get(url).send().await?;
-    let text = response.text().await?;
-    
-    extract_page_id_from_html(&text)
+    FacebookTotem::new()?.get_id_from_url(url).await
 }
 
+/// The page ID in a page's HTML, from the most reliable place it was found
+/// (see [`PageIdSource::is_reliable`]). Failing that, a `"page_id"` is used
+/// only if it is the one there; a `"userID"` never is. See
+/// [`page_id_candidates`] for every ID found and where it came from. A login
+/// wall, checkpoint or consent page is reported as a [`FacebookError`].
 pub fn extract_page_id_from_html(html: &str) -> Result<String> {
-    let start = html.find("[{\"pageID\":\"").ok_or_else(|| anyhow::anyhow!("Could not find pageID in response"))?;
-    let start = start + "[{\"pageID\":\"".len();
-    let end = html[start..].find('"').ok_or_else(|| anyhow::anyhow!("Could not find end of pageID"))?;
-    
-    Ok(html[start..start + end].to_string())
+    page_id_from_html(None, html)
+}
+
+/// [`extract_page_id_from_html`] for a page fetched from `url`, where a
+/// redirect to the login or checkpoint pages says more than the HTML.
+pub(crate) fn page_id_from_html(url: Option<&str>, html: &str) -> Result<String> {
+    let candidates = page_id_candidates(html);
+    if let Some(candidate) = candidates.iter().find(|candidate| candidate.source.is_reliable()) {
+        return Ok(candidate.page_id.clone());
+    }
+    // Interstitials carry the viewer's ID, so rule them out before guessing.
+    if let Some(facebook_error) = detect_interstitial(url, html) {
+        return Err(facebook_error.into());
+    }
+    let snake_case: Vec<&PageIdCandidate> =
+        candidates.iter().filter(|candidate| candidate.source == PageIdSource::PageIdSnakeCase).collect();
+    match (snake_case.as_slice(), candidates.is_empty()) {
+        ([only], _) => Ok(only.page_id.clone()),
+        (_, true) => Err(anyhow::anyhow!("Could not find pageID in response")),
+        (_, false) => {
+            let guesses: Vec<String> = candidates.iter().map(|candidate| format!("{} ({:?})", candidate.page_id, candidate.source)).collect();
+            Err(anyhow::anyhow!("Could not find a reliable pageID in response, only {}", guesses.join(", ")))
+        }
+    }
 }
 
 pub async fn get_facebook_page_from_name(name: &str) -> Result<Vec<FacebookPage>> {
-    get_facebook_page_from_name_with_client(name, &build_client().await?).await
+    FacebookTotem::new()?.get_facebook_page_from_name(name).await
 }
 
-pub async fn get_facebook_page_from_name_with_client(name: &str, client: &Client) -> Result<Vec<FacebookPage>> {
-    let ua = UserAgents::new();
-    let user_agent = ua.random();
-    
-    let mut headers = reqwest::header::HeaderMap::new();
-    headers.insert("User-Agent", user_agent.parse()?);
-    headers.insert("Accept", "*/*".parse()?);
-    headers.insert("Accept-Language", "en,en-US;q=0.5".parse()?);
-    headers.insert("Referer", "https://www.facebook.com/ads/library/".parse()?);
-    headers.insert("Content-Type", "application/x-www-form-urlencoded".parse()?);
-    headers.insert("Origin", "https://www.facebook.com".parse()?);
-    headers.insert("DNT", "1".parse()?);
-    headers.insert("Connection", "keep-alive".parse()?);
-    headers.insert("TE", "Trailers".parse()?);
-    
-    let mut params = HashMap::new();
-    params.insert("ad_type", "all");
-    params.insert("country", "");
-    params.insert("is_mobile", "false");
-    params.insert("q", name);
-    params.insert("session_id", "\"\"");
-    
-    let data = build_facebook_form_data();
-    
-    let response = client
-        .post("https://www.facebook.com/ads/library/async/search_typeahead/")
-        .headers(headers)
-        .query(&params)
-        .form(&data)
-        .send()
-        .await?;
-    
-    let text = response.text().await?;
-    parse_facebook_search_response(&text)
+/// Fetch every ad for a page matching `query` with a default [`FacebookTotem`] client.
+pub async fn get_ads_from_id(id: &str, query: &AdQuery) -> Result<Vec<FacebookAd>> {
+    FacebookTotem::new()?.get_ads_from_id(id, query).await
 }
 
-pub async fn get_ads_from_id(id: &str) -> Result<Vec<Value>> {
-    get_ads_from_id_with_client(id, &build_client().await?).await
+/// How the words of a keyword query are matched against ad text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeywordMatch {
+    /// Ads containing the query as an exact phrase.
+    ExactPhrase,
+    /// Ads containing any of the query's words.
+    #[default]
+    AnyWord,
 }
 
-pub async fn get_ads_from_id_with_client(id: &str, client: &Client) -> Result<Vec<Value>> {
-    let ua = UserAgents::new();
-    let user_agent = ua.random();
-    
-    let mut headers = reqwest::header::HeaderMap::new();
-    headers.insert("User-Agent", user_agent.parse()?);
-    headers.insert("Accept", "*/*".parse()?);
-    headers.insert("Accept-Language", "en,en-US;q=0.5".parse()?);
-    headers.insert("Referer", "https://www.facebook.com/ads/library/".parse()?);
-    headers.insert("Content-Type", "application/x-www-form-urlencoded".parse()?);
-    headers.insert("Origin", "https://www.facebook.com".parse()?);
-    headers.insert("DNT", "1".parse()?);
-    headers.insert("Connection", "keep-alive".parse()?);
-    headers.insert("Cache-Control", "max-age=0".parse()?);
-    
-    let mut params = HashMap::new();
-    params.insert("session_id", "\"\"");
-    params.insert("count", "30");
-    params.insert("active_status", "all");
-    params.insert("ad_type", "all");
-    params.insert("countries[0]", "ALL");
-    params.insert("impression_search_field", "has_impressions_lifetime");
-    params.insert("view_all_page_id", id);
-    params.insert("sort_data[direction]", "desc");
-    params.insert("sort_data[mode]", "relevancy_monthly_grouped");
-    
-    let data = build_facebook_form_data();
-    
-    let response = client
-        .post("https://www.facebook.com/ads/library/async/search_ads/")
-        .headers(headers)
-        .query(&params)
-        .form(&data)
-        .send()
-        .await?;
-    
-    let text = response.text().await?;
-    parse_facebook_ads_response(&text)
+impl KeywordMatch {
+    pub(crate) fn as_search_type(self) -> &'static str {
+        match self {
+            KeywordMatch::ExactPhrase => "keyword_exact_phrase",
+            KeywordMatch::AnyWord => "keyword_unordered",
+        }
+    }
+}
+
+/// Search ads across all pages by keyword with a default [`FacebookTotem`] client.
+pub async fn search_ads_by_keyword(keywords: &str, keyword_match: KeywordMatch, query: &AdQuery) -> Result<Vec<FacebookAd>> {
+    FacebookTotem::new()?.search_ads_by_keyword(keywords, keyword_match, query).await
 }
 
 pub fn parse_facebook_search_response(text: &str) -> Result<Vec<FacebookPage>> {
     let cleaned_text = text.replace("for (;;);", "");
-    let parsed: FacebookPageSearchResponse = serde_json::from_str(&cleaned_text)?;
+    let parsed: FacebookPageSearchResponse = serde_json::from_str(&cleaned_text).map_err(|err| explain_parse_error(text, err))?;
     Ok(parsed.payload.page_results)
 }
 
 pub fn parse_facebook_ads_response(text: &str) -> Result<Vec<Value>> {
+    Ok(parse_facebook_ads_payload(text)?.into_ads())
+}
+
+/// Parse a `search_ads` response, keeping the pagination fields alongside the results.
+pub fn parse_facebook_ads_payload(text: &str) -> Result<FacebookAdsPayload> {
     let cleaned_text = text.replace("for (;;);", "");
-    let parsed: FacebookAdsResponse = serde_json::from_str(&cleaned_text)?;
-    
-    let mut result = Vec::new();
-    for res in parsed.payload.results {
-        for re in res {
-            result.push(re);
+    let parsed: FacebookAdsResponse = serde_json::from_str(&cleaned_text).map_err(|err| explain_parse_error(text, err))?;
+    Ok(parsed.payload)
+}
+
+/// File formats results can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    /// One pretty-printed JSON array.
+    Json,
+    /// One compact JSON object per line.
+    Jsonl,
+    /// Normalized, upserted tables in a SQLite database. See [`SqliteStore`].
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+    /// Typed columns in an Apache Parquet file. See [`ads_schema`].
+    #[cfg(feature = "parquet")]
+    Parquet,
+    /// Typed columns in an Arrow IPC (Feather v2) file.
+    #[cfg(feature = "parquet")]
+    Arrow,
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Json => "json",
+            OutputFormat::Jsonl => "jsonl",
+            #[cfg(feature = "sqlite")]
+            OutputFormat::Sqlite => "sqlite",
+            #[cfg(feature = "parquet")]
+            OutputFormat::Parquet => "parquet",
+            #[cfg(feature = "parquet")]
+            OutputFormat::Arrow => "arrow",
         }
     }
-    
-    Ok(result)
 }
 
+/// Settings shared by the CSV and JSON writers.
+#[derive(Debug, Clone, Default)]
+pub struct OutputOptions {
+    /// Expand nested objects and arrays into dotted columns instead of JSON cells.
+    pub flatten: Option<FlattenOptions>,
+    /// Only write these columns, in this order. Paths are looked up after flattening.
+    pub fields: Option<Vec<Field>>,
+}
+
+/// Write rows to CSV. The header is the union of every row's keys, in the
+/// order they are first seen; rows missing a key get a blank cell.
 pub fn write_json_to_csv(data: &[Value], filename: &str) -> Result<()> {
-    if data.is_empty() {
-        return Ok(());
+    write_json_to_csv_with(data, filename, &OutputOptions::default())
+}
+
+pub fn write_json_to_csv_with(data: &[Value], filename: &str, options: &OutputOptions) -> Result<()> {
+    write_json_to_sink(data, &mut CsvSink::create(filename, options.clone()))
+}
+
+/// Write rows as one pretty-printed JSON array.
+pub fn write_json(data: &[Value], filename: &str) -> Result<()> {
+    write_json_with(data, filename, &OutputOptions::default())
+}
+
+pub fn write_json_with(data: &[Value], filename: &str, options: &OutputOptions) -> Result<()> {
+    write_json_to_sink(data, &mut JsonSink::create(filename, options.clone()))
+}
+
+/// Write rows as JSON Lines, one object per line.
+pub fn write_jsonl(data: &[Value], filename: &str) -> Result<()> {
+    write_jsonl_with(data, filename, &OutputOptions::default())
+}
+
+pub fn write_jsonl_with(data: &[Value], filename: &str, options: &OutputOptions) -> Result<()> {
+    write_json_to_sink(data, &mut JsonlSink::create(filename, options.clone()))
+}
+
+/// Write rows in `format`. Arbitrary rows have no schema for SQLite, Parquet
+/// or Arrow; use [`write_ads`] or [`write_facebook_pages`] for those.
+pub fn write_rows(data: &[Value], filename: &str, format: OutputFormat, options: &OutputOptions) -> Result<()> {
+    write_json_to_sink(data, &mut create_sink(format, filename, options)?)
+}
+
+/// The built-in sink for a record-by-record `format`, writing to `filename`.
+pub fn create_sink(format: OutputFormat, filename: &str, options: &OutputOptions) -> Result<Box<dyn OutputSink>> {
+    let options = options.clone();
+    match format {
+        OutputFormat::Csv => Ok(Box::new(CsvSink::create(filename, options))),
+        OutputFormat::Json => Ok(Box::new(JsonSink::create(filename, options))),
+        OutputFormat::Jsonl => Ok(Box::new(JsonlSink::create(filename, options))),
+        #[allow(unreachable_patterns)]
+        _ => Err(anyhow::anyhow!("only ads and pages can be written as {}", format.extension())),
     }
-    
-    let file = File::create(filename)?;
-    let mut wtr = Writer::from_writer(file);
-    
-    if let Some(first_item) = data.first() {
-        if let Value::Object(obj) = first_item {
-            let headers: Vec<&str> = obj.keys().map(|k| k.as_str()).collect();
-            wtr.write_record(&headers)?;
-            
-            for item in data {
-                if let Value::Object(obj) = item {
-                    let mut record = Vec::new();
-                    for header in &headers {
-                        let value = obj.get(*header)
-                            .map(|v| match v {
-                                Value::String(s) => s.clone(),
-                                Value::Number(n) => n.to_string(),
-                                Value::Bool(b) => b.to_string(),
-                                Value::Null => String::new(),
-                                _ => serde_json::to_string(v).unwrap_or_default(),
-                            })
-                            .unwrap_or_default();
-                        record.push(value);
-                    }
-                    wtr.write_record(&record)?;
+}
+
+/// The built-in sink for a record-by-record `format`, writing to `writer`,
+/// e.g. `std::io::stdout()`.
+pub fn create_writer_sink<W: Write + 'static>(format: OutputFormat, writer: W, options: &OutputOptions) -> Result<Box<dyn OutputSink>> {
+    let options = options.clone();
+    match format {
+        OutputFormat::Csv => Ok(Box::new(CsvSink::new(writer, options))),
+        OutputFormat::Json => Ok(Box::new(JsonSink::new(writer, options))),
+        OutputFormat::Jsonl => Ok(Box::new(JsonlSink::new(writer, options))),
+        #[allow(unreachable_patterns)]
+        _ => Err(anyhow::anyhow!("{} output can only be written to a file", format.extension())),
+    }
+}
+
+/// Flatten every row, keeping the spend and impressions bound columns of ads
+/// since the objects they are derived from get split up.
+pub fn flatten_rows(data: &[Value], options: &FlattenOptions) -> Result<Vec<Value>> {
+    let mut rows = Vec::new();
+    for item in data {
+        let ranges = has_range_fields(item).then(|| range_column_values(item));
+        rows.extend(flatten_value(item, options)?.into_iter().map(|mut row| {
+            if let Some(ranges) = &ranges {
+                for (column, value) in RANGE_COLUMNS.iter().zip(ranges) {
+                    row.insert(column.to_string(), Value::String(value.clone()));
                 }
             }
-        }
+            Value::Object(row)
+        }));
+    }
+    Ok(rows)
+}
+
+/// Write ads to CSV using every field of their raw response objects.
+pub fn write_ads_to_csv(ads: &[FacebookAd], filename: &str) -> Result<()> {
+    write_ads_to_csv_with(ads, filename, &OutputOptions::default())
+}
+
+pub fn write_ads_to_csv_with(ads: &[FacebookAd], filename: &str, options: &OutputOptions) -> Result<()> {
+    write_ads(ads, filename, OutputFormat::Csv, options)
+}
+
+/// Write ads as a JSON array of their raw response objects.
+pub fn write_ads_to_json(ads: &[FacebookAd], filename: &str) -> Result<()> {
+    write_ads(ads, filename, OutputFormat::Json, &OutputOptions::default())
+}
+
+/// Write ads as JSON Lines of their raw response objects.
+pub fn write_ads_to_jsonl(ads: &[FacebookAd], filename: &str) -> Result<()> {
+    write_ads(ads, filename, OutputFormat::Jsonl, &OutputOptions::default())
+}
+
+/// Write ads in `format`. `options` don't apply to SQLite, Parquet and
+/// Arrow, which always store the typed fields and the raw object.
+pub fn write_ads(ads: &[FacebookAd], filename: &str, format: OutputFormat, options: &OutputOptions) -> Result<()> {
+    match format {
+        #[cfg(feature = "sqlite")]
+        OutputFormat::Sqlite => write_ads_to_sqlite(ads, filename),
+        #[cfg(feature = "parquet")]
+        OutputFormat::Parquet => write_ads_to_parquet(ads, filename),
+        #[cfg(feature = "parquet")]
+        OutputFormat::Arrow => write_ads_to_arrow(ads, filename),
+        _ => write_ads_to_sink(ads, &mut create_sink(format, filename, options)?),
     }
-    
-    wtr.flush()?;
-    Ok(())
 }
 
 pub fn write_facebook_pages_to_csv(pages: &[FacebookPage], filename: &str) -> Result<()> {
-    let json_values: Vec<Value> = pages.iter()
-        .map(|page| serde_json::to_value(page).unwrap_or(Value::Null))
-        .collect();
-    
-    write_json_to_csv(&json_values, filename)
+    write_facebook_pages(pages, filename, OutputFormat::Csv)
+}
+
+pub fn write_facebook_pages_to_json(pages: &[FacebookPage], filename: &str) -> Result<()> {
+    write_facebook_pages(pages, filename, OutputFormat::Json)
+}
+
+pub fn write_facebook_pages_to_jsonl(pages: &[FacebookPage], filename: &str) -> Result<()> {
+    write_facebook_pages(pages, filename, OutputFormat::Jsonl)
 }
 
-async fn build_client() -> Result<Client> {
-    let jar = Arc::new(Jar::default());
-    let client = Client::builder()
-        .cookie_provider(jar)
-        .build()?;
-    Ok(client)
+pub fn write_facebook_pages(pages: &[FacebookPage], filename: &str, format: OutputFormat) -> Result<()> {
+    match format {
+        #[cfg(feature = "sqlite")]
+        OutputFormat::Sqlite => write_facebook_pages_to_sqlite(pages, filename),
+        #[cfg(feature = "parquet")]
+        OutputFormat::Parquet => write_facebook_pages_to_parquet(pages, filename),
+        #[cfg(feature = "parquet")]
+        OutputFormat::Arrow => write_facebook_pages_to_arrow(pages, filename),
+        _ => write_facebook_pages_to_sink(pages, &mut create_sink(format, filename, &OutputOptions::default())?),
+    }
 }
 
-fn build_facebook_form_data() -> HashMap<&'static str, &'static str> {
+/// Form body for the Ad Library's async endpoints. Session tokens that are
+/// missing fall back to the empty placeholders the endpoints used to accept.
+fn build_facebook_form_data(session: Option<&SessionTokens>) -> HashMap<&'static str, String> {
+    let token = |pick: fn(&SessionTokens) -> &Option<String>| {
+        session
+            .and_then(|tokens| pick(tokens).clone())
+            .unwrap_or_else(|| "\"\"".to_string())
+    };
+    
     let mut data = HashMap::new();
-    data.insert("__user", "0");
-    data.insert("__a", "1");
-    data.insert("__dyn", "\"\"");
-    data.insert("__csr", "");
-    data.insert("__req", "1");
-    data.insert("__beoa", "0");
-    data.insert("__pc", "PHASED:DEFAULT");
-    data.insert("dpr", "1");
-    data.insert("__ccg", "UNKNOWN");
-    data.insert("__rev", "\"\"");
-    data.insert("__s", "\"\"");
-    data.insert("__hsi", "\"\"");
-    data.insert("__comet_req", "0");
-    data.insert("lsd", "\"\"");
-    data.insert("jazoest", "\"\"");
-    data.insert("__spin_r", "\"\"");
-    data.insert("__spin_b", "trunk");
-    data.insert("__spin_t", "\"\"");
+    data.insert("__user", "0".to_string());
+    data.insert("__a", "1".to_string());
+    data.insert("__dyn", "\"\"".to_string());
+    data.insert("__csr", String::new());
+    data.insert("__req", "1".to_string());
+    data.insert("__beoa", "0".to_string());
+    data.insert("__pc", "PHASED:DEFAULT".to_string());
+    data.insert("dpr", "1".to_string());
+    data.insert("__ccg", "UNKNOWN".to_string());
+    data.insert("__rev", token(|t| &t.rev));
+    data.insert("__s", "\"\"".to_string());
+    data.insert("__hsi", token(|t| &t.hsi));
+    data.insert("__comet_req", "0".to_string());
+    data.insert("lsd", token(|t| &t.lsd));
+    data.insert("jazoest", token(|t| &t.jazoest));
+    data.insert("__spin_r", token(|t| &t.spin_r));
+    data.insert("__spin_b", "trunk".to_string());
+    data.insert("__spin_t", token(|t| &t.spin_t));
     data
 }
 
@@ -243,6 +415,7 @@ mod tests {
     use super::*;
     use serde_json::json;
     use tempfile::NamedTempFile;
+    use std::fs::File;
     use std::io::Read;
 
     #[test]
@@ -252,6 +425,32 @@ mod tests {
         assert_eq!(result, "123456789");
     }
 
+    #[test]
+    fn test_extract_page_id_ignores_viewer_user_id() {
+        let html = r#"<script>{"USER_ID":"100000000000001","userID":"100000000000001"}</script>
+            <script>{"pageID":"123456789"}</script>"#;
+        assert_eq!(extract_page_id_from_html(html).unwrap(), "123456789");
+
+        let err = extract_page_id_from_html(r#"<script>{"userID":"100000000000001"}</script>"#).unwrap_err();
+        assert!(err.to_string().contains("100000000000001 (UserId)"), "{}", err);
+    }
+
+    #[test]
+    fn test_extract_page_id_only_trusts_a_lone_page_id() {
+        assert_eq!(extract_page_id_from_html(r#"{"page_id":"111111111","x":{"page_id":"111111111"}}"#).unwrap(), "111111111");
+        assert!(extract_page_id_from_html(r#"{"page_id":"111111111","related":[{"page_id":"222222222"}]}"#).is_err());
+    }
+
+    #[test]
+    fn test_extract_page_id_reports_login_wall_with_user_id() {
+        let html = r#"<html><title>Log into Facebook</title><script>{"userID":"100000000000001"}</script>
+            <form id="login_form"></form></html>"#;
+
+        let err = extract_page_id_from_html(html).unwrap_err();
+
+        assert_eq!(err.downcast_ref::<FacebookError>(), Some(&FacebookError::LoginWall { url: None }));
+    }
+
     #[test]
     fn test_extract_page_id_from_html_not_found() {
         let html = r#"<html>no page id here</html>"#;
@@ -279,6 +478,35 @@ mod tests {
         assert_eq!(result[0]["content"], "test ad");
     }
 
+    #[test]
+    fn test_parse_facebook_ads_payload_with_cursor() {
+        let response_text = r#"for (;;);{"payload":{"results":[[{"ad_id":"1"}],[{"ad_id":"2"}]],"forwardCursor":"abc","collationToken":"tok","isResultComplete":false,"totalCount":75}}"#;
+        let payload = parse_facebook_ads_payload(response_text).unwrap();
+        
+        assert_eq!(payload.next_cursor(), Some("abc"));
+        assert_eq!(payload.collation_token.as_deref(), Some("tok"));
+        assert_eq!(payload.total_count, Some(75));
+        assert_eq!(payload.into_ads().len(), 2);
+    }
+
+    #[test]
+    fn test_parse_facebook_ads_payload_complete() {
+        let response_text = r#"for (;;);{"payload":{"results":[],"forwardCursor":"abc","isResultComplete":true}}"#;
+        let payload = parse_facebook_ads_payload(response_text).unwrap();
+        assert_eq!(payload.next_cursor(), None);
+        
+        let response_text = r#"for (;;);{"payload":{"results":[],"forwardCursor":""}}"#;
+        let payload = parse_facebook_ads_payload(response_text).unwrap();
+        assert_eq!(payload.next_cursor(), None);
+    }
+
+    #[test]
+    fn test_keyword_match_search_type() {
+        assert_eq!(KeywordMatch::ExactPhrase.as_search_type(), "keyword_exact_phrase");
+        assert_eq!(KeywordMatch::AnyWord.as_search_type(), "keyword_unordered");
+        assert_eq!(KeywordMatch::default(), KeywordMatch::AnyWord);
+    }
+
     #[test]
     fn test_write_json_to_csv() {
         let data = vec![
@@ -300,6 +528,149 @@ mod tests {
         assert!(file_content.contains("Jane,25") || file_content.contains("25,Jane"));
     }
 
+    #[test]
+    fn test_write_json_to_csv_unions_headers() {
+        let data = vec![
+            json!({"b": 1, "a": "x"}),
+            json!({"a": "y", "c": true}),
+            json!("loose value"),
+            json!({"d": null}),
+        ];
+        
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_path = temp_file.path().to_str().unwrap();
+        
+        write_json_to_csv(&data, temp_path).unwrap();
+        
+        let file_content = std::fs::read_to_string(temp_path).unwrap();
+        let lines: Vec<&str> = file_content.lines().collect();
+        assert_eq!(lines, vec![
+            "a,b,c,value,d",
+            "x,1,,,",
+            "y,,true,,",
+            ",,,loose value,",
+            ",,,,",
+        ]);
+    }
+
+    #[test]
+    fn test_write_json_to_csv_range_columns() {
+        let data = vec![
+            json!({"adArchiveID": "1", "spend": "<100", "impressionsWithIndex": {"impressionsText": "1K-5K"}}),
+            json!({"adArchiveID": "2", "spend": {"lower_bound": "100", "upper_bound": "199"}}),
+        ];
+        
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_path = temp_file.path().to_str().unwrap();
+        
+        write_json_to_csv(&data, temp_path).unwrap();
+        
+        let file_content = std::fs::read_to_string(temp_path).unwrap();
+        let lines: Vec<&str> = file_content.lines().collect();
+        assert!(lines[0].ends_with("spend_lower,spend_upper,impressions_lower,impressions_upper"));
+        assert!(lines[1].ends_with(",0,99,1000,5000"));
+        assert!(lines[2].ends_with(",100,199,,"));
+    }
+
+    #[test]
+    fn test_write_json_to_csv_flattened() {
+        let data = vec![
+            json!({"adArchiveID": "1", "spend": {"lower_bound": "100", "upper_bound": "199"}, "snapshot": {"body": {"text": "Hi"}, "cards": [{"title": "A"}]}}),
+        ];
+        let options = OutputOptions { flatten: Some(FlattenOptions::new()), ..Default::default() };
+        
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_path = temp_file.path().to_str().unwrap();
+        
+        write_json_to_csv_with(&data, temp_path, &options).unwrap();
+        
+        let file_content = std::fs::read_to_string(temp_path).unwrap();
+        let lines: Vec<&str> = file_content.lines().collect();
+        assert_eq!(lines[0], "adArchiveID,impressions_lower,impressions_upper,snapshot.body.text,snapshot.cards.0.title,spend.lower_bound,spend.upper_bound,spend_lower,spend_upper");
+        assert_eq!(lines[1], "1,,,Hi,A,100,199,100,199");
+    }
+
+    #[test]
+    fn test_write_json_to_csv_with_fields() {
+        let data = vec![
+            json!({"adArchiveID": "1", "snapshot": {"title": "A", "cards": [{"title": "C"}]}, "spend": {"lower_bound": "100"}}),
+            json!({"adArchiveID": "2", "snapshot": {"title": "B"}}),
+        ];
+        let fields = parse_fields("snapshot.title:title,adArchiveID:id,spend.lower_bound,snapshot.cards.0.title").unwrap();
+        let options = OutputOptions { fields: Some(fields), ..Default::default() };
+        
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_path = temp_file.path().to_str().unwrap();
+        
+        write_json_to_csv_with(&data, temp_path, &options).unwrap();
+        
+        let file_content = std::fs::read_to_string(temp_path).unwrap();
+        let lines: Vec<&str> = file_content.lines().collect();
+        assert_eq!(lines, vec![
+            "title,id,spend.lower_bound,snapshot.cards.0.title",
+            "A,1,100,C",
+            "B,2,,",
+        ]);
+        
+        let options = OutputOptions { fields: Some(parse_fields("adArchiveID,snapshot.titel").unwrap()), ..Default::default() };
+        let err = write_json_to_csv_with(&data, temp_path, &options).unwrap_err();
+        assert!(err.to_string().contains("snapshot.titel"));
+    }
+
+    #[test]
+    fn test_write_ads_to_json_and_jsonl() {
+        let ads: Vec<FacebookAd> = vec![
+            json!({"adArchiveID": "1", "isActive": true, "snapshot": {"cards": [{"title": "A"}]}}).into(),
+            json!({"adArchiveID": "2", "startDate": 1700000000}).into(),
+        ];
+        let temp_dir = tempfile::tempdir().unwrap();
+        
+        let json_path = temp_dir.path().join("ads.json");
+        write_ads_to_json(&ads, json_path.to_str().unwrap()).unwrap();
+        let written: Vec<Value> = serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+        assert_eq!(written, vec![ads[0].raw.clone(), ads[1].raw.clone()]);
+        
+        let jsonl_path = temp_dir.path().join("ads.jsonl");
+        write_ads_to_jsonl(&ads, jsonl_path.to_str().unwrap()).unwrap();
+        let content = std::fs::read_to_string(&jsonl_path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(serde_json::from_str::<Value>(lines[0]).unwrap(), ads[0].raw);
+        assert_eq!(lines[1], r#"{"adArchiveID":"2","startDate":1700000000}"#);
+    }
+
+    #[test]
+    fn test_write_jsonl_with_fields() {
+        let data = vec![json!({"adArchiveID": "1", "snapshot": {"title": "A"}})];
+        let options = OutputOptions { fields: Some(parse_fields("adArchiveID:id,snapshot.title").unwrap()), ..Default::default() };
+        
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_path = temp_file.path().to_str().unwrap();
+        
+        write_jsonl_with(&data, temp_path, &options).unwrap();
+        
+        let file_content = std::fs::read_to_string(temp_path).unwrap();
+        assert_eq!(file_content, "{\"id\":\"1\",\"snapshot.title\":\"A\"}\n");
+    }
+
+    #[test]
+    fn test_write_facebook_pages_to_json() {
+        let pages = vec![FacebookPage {
+            page_id: Some("123".to_string()),
+            page_name: Some("Test Page".to_string()),
+            page_profile_picture_uri: None,
+            page_uri: None,
+        }];
+        
+        let temp_file = NamedTempFile::new().unwrap();
+        let temp_path = temp_file.path().to_str().unwrap();
+        
+        write_facebook_pages_to_json(&pages, temp_path).unwrap();
+        
+        let written: Vec<FacebookPage> = serde_json::from_str(&std::fs::read_to_string(temp_path).unwrap()).unwrap();
+        assert_eq!(written, pages);
+    }
+
     #[test]
     fn test_write_facebook_pages_to_csv() {
         let pages = vec![
@@ -326,13 +697,26 @@ mod tests {
 
     #[test]
     fn test_build_facebook_form_data() {
-        let data = build_facebook_form_data();
+        let data = build_facebook_form_data(None);
         
-        assert_eq!(data.get("__user"), Some(&"0"));
-        assert_eq!(data.get("__a"), Some(&"1"));
-        assert_eq!(data.get("__spin_b"), Some(&"trunk"));
+        assert_eq!(data.get("__user").map(String::as_str), Some("0"));
+        assert_eq!(data.get("__a").map(String::as_str), Some("1"));
+        assert_eq!(data.get("__spin_b").map(String::as_str), Some("trunk"));
         assert!(data.contains_key("__dyn"));
-        assert!(data.contains_key("lsd"));
+        assert_eq!(data.get("lsd").map(String::as_str), Some("\"\""));
+    }
+
+    #[test]
+    fn test_build_facebook_form_data_with_session() {
+        let session = extract_session_tokens(r#"["LSD",[],{"token":"AVqToken"},323]{"__spin_r":1012345678,"__spin_t":1700000000,"hsi":"730"}"#);
+        let data = build_facebook_form_data(Some(&session));
+        
+        assert_eq!(data.get("lsd").map(String::as_str), Some("AVqToken"));
+        assert_eq!(data.get("__spin_r").map(String::as_str), Some("1012345678"));
+        assert_eq!(data.get("__spin_t").map(String::as_str), Some("1700000000"));
+        assert_eq!(data.get("__rev").map(String::as_str), Some("1012345678"));
+        assert_eq!(data.get("__hsi").map(String::as_str), Some("730"));
+        assert!(!data["jazoest"].is_empty());
     }
 
     #[tokio::test]
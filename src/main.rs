@@ -1,10 +1,45 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
-use csv::Reader;
-use facebook_totem::{get_id_from_url, get_ads_from_id, get_facebook_page_from_name, write_json_to_csv, write_facebook_pages_to_csv};
+use chrono::NaiveDate;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use facebook_totem::{
+    create_sink, create_writer_sink, read_targets, write_ads, write_facebook_pages, write_facebook_pages_to_sink, write_report,
+    ActiveStatus, AdLibraryBackend, AdQuery, AdType, ArrayMode, Checkpoint, CheckpointEntry, FacebookAd, FacebookTotem, Field,
+    FilenameTemplate, FilenameValues, FlattenOptions, GraphApiBackend, KeywordMatch, MediaType, OutputFormat, OutputOptions,
+    OutputSink, PublisherPlatform, RateLimiter, ScraperBackend, SortDirection, SortMode, Target, TargetStatus, UserAgentPolicy,
+    DEFAULT_BASE_URL,
+};
+#[cfg(feature = "sqlite")]
+use facebook_totem::SqliteStore;
+use futures::stream::{self, BoxStream, StreamExt};
 use indicatif::{ProgressBar, ProgressStyle};
-use std::collections::HashMap;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::num::NonZeroUsize;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// `--output` value that writes to stdout instead of a file.
+const STDOUT: &str = "-";
+
+/// How multi mode names each page's file without `--filename-template`.
+const DEFAULT_MULTI_TEMPLATE: &str = "{username}_{page_id}.{ext}";
+
+/// Set when the output goes to stdout, so status messages move to stderr.
+static STATUS_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// `println!` for status messages, which must not end up in piped output.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if STATUS_TO_STDERR.load(Ordering::Relaxed) {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -12,8 +47,221 @@ struct Cli {
     #[command(subcommand)]
     mode: Mode,
     
+    /// Name of the output file, or - for stdout
     #[arg(short, long)]
     output: String,
+    
+    /// Directory output files are written to
+    #[arg(long, default_value = "output", global = true)]
+    output_dir: String,
+    
+    /// Name output files from {username}, {page_id}, {query}, {mode}, {date} and {ext}
+    #[arg(long, global = true)]
+    filename_template: Option<FilenameTemplate>,
+    
+    /// Output file format
+    #[arg(long, value_enum, default_value_t = Format::Csv, global = true)]
+    format: Format,
+    
+    /// Where ads are fetched from
+    #[arg(long, value_enum, default_value_t = Backend::Scraper, global = true)]
+    backend: Backend,
+    
+    /// Graph API access token, required with --backend graph
+    #[arg(long, global = true)]
+    access_token: Option<String>,
+    
+    /// Facebook base URL, e.g. to point at a mock server
+    #[arg(long, default_value = DEFAULT_BASE_URL, global = true)]
+    base_url: String,
+    
+    /// Send this User-Agent instead of a random browser one
+    #[arg(long, global = true)]
+    user_agent: Option<String>,
+    
+    /// Per-request timeout in seconds
+    #[arg(long, global = true)]
+    timeout: Option<u64>,
+    
+    /// Send at most this many requests per second, across all workers
+    #[arg(long, global = true)]
+    requests_per_second: Option<f64>,
+    
+    /// Expand nested ad fields into dotted CSV columns (snapshot.body.text)
+    #[arg(long, global = true)]
+    flatten: bool,
+    
+    /// Maximum number of path segments per flattened column
+    #[arg(long, global = true, requires = "flatten")]
+    flatten_depth: Option<NonZeroUsize>,
+    
+    /// How flattened arrays are written
+    #[arg(long, value_enum, default_value_t = Arrays::Index, global = true, requires = "flatten")]
+    arrays: Arrays,
+    
+    /// Only write these ad columns, in order, e.g. adArchiveID,snapshot.title:title
+    #[arg(long, value_delimiter = ',', global = true)]
+    fields: Vec<Field>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Csv,
+    /// One JSON array
+    Json,
+    /// One JSON object per line
+    Jsonl,
+    /// Pages, ads and ad cards upserted into a SQLite database
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+    /// Typed columns in an Apache Parquet file
+    #[cfg(feature = "parquet")]
+    Parquet,
+    /// Typed columns in an Arrow IPC (Feather) file
+    #[cfg(feature = "parquet")]
+    Arrow,
+}
+
+impl From<Format> for OutputFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Csv => OutputFormat::Csv,
+            Format::Json => OutputFormat::Json,
+            Format::Jsonl => OutputFormat::Jsonl,
+            #[cfg(feature = "sqlite")]
+            Format::Sqlite => OutputFormat::Sqlite,
+            #[cfg(feature = "parquet")]
+            Format::Parquet => OutputFormat::Parquet,
+            #[cfg(feature = "parquet")]
+            Format::Arrow => OutputFormat::Arrow,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Arrays {
+    /// One column per element (snapshot.cards.0.title)
+    Index,
+    /// All elements joined into one cell
+    Join,
+    /// One row per element
+    Explode,
+}
+
+impl Cli {
+    fn build_totem(&self) -> Result<FacebookTotem> {
+        let mut builder = FacebookTotem::builder().base_url(&self.base_url);
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(UserAgentPolicy::Fixed(user_agent.clone()));
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(Duration::from_secs(timeout));
+        }
+        if let Some(requests) = self.requests_per_second {
+            builder = builder.rate_limiter(Arc::new(RateLimiter::per_second(requests)?));
+        }
+        builder.build()
+    }
+    
+    fn output_options(&self) -> OutputOptions {
+        let flatten = self.flatten.then(|| {
+            let arrays = match self.arrays {
+                Arrays::Index => ArrayMode::Index,
+                Arrays::Join => ArrayMode::Join,
+                Arrays::Explode => ArrayMode::Explode,
+            };
+            let options = FlattenOptions::new().arrays(arrays);
+            match self.flatten_depth {
+                Some(depth) => options.max_depth(depth.get()),
+                None => options,
+            }
+        });
+        let fields = (!self.fields.is_empty()).then(|| self.fields.clone());
+        OutputOptions { flatten, fields }
+    }
+    
+    fn writes_to_stdout(&self) -> bool {
+        self.output == STDOUT
+    }
+    
+    /// Where single, search and keywords mode write: the rendered
+    /// `--filename-template` if given, otherwise `--output`.
+    fn output_path(&self, values: &FilenameValues) -> Result<String> {
+        if self.writes_to_stdout() {
+            return Ok(STDOUT.to_string());
+        }
+        match &self.filename_template {
+            Some(template) => self.in_output_dir(&template.render(values)?),
+            None => self.in_output_dir(&self.output),
+        }
+    }
+    
+    /// Where multi mode writes a page's ads. Each page gets its own file, named
+    /// by `--filename-template` or `{username}_{page_id}.{ext}`, except that
+    /// every page goes into the same SQLite database unless a template is given.
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+    fn multi_output_path(&self, format: OutputFormat, values: &FilenameValues) -> Result<String> {
+        if self.writes_to_stdout() {
+            return Ok(STDOUT.to_string());
+        }
+        #[cfg(feature = "sqlite")]
+        if format == OutputFormat::Sqlite && self.filename_template.is_none() {
+            return self.in_output_dir(&self.output);
+        }
+        let template = match &self.filename_template {
+            Some(template) => template.clone(),
+            None => DEFAULT_MULTI_TEMPLATE.parse()?,
+        };
+        self.in_output_dir(&template.render(values)?)
+    }
+    
+    /// `name` under `--output-dir`, creating any missing directories.
+    fn in_output_dir(&self, name: &str) -> Result<String> {
+        let path = Path::new(&self.output_dir).join(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(path.to_string_lossy().into_owned())
+    }
+    
+    /// An HTTP client for the Graph API with the same timeout and
+    /// User-Agent as the scraper's.
+    fn graph_client(&self) -> Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder();
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent.as_str());
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(Duration::from_secs(timeout));
+        }
+        Ok(builder.build()?)
+    }
+    
+    fn build_backend(&self, totem: Arc<FacebookTotem>) -> Result<Box<dyn AdLibraryBackend>> {
+        match self.backend {
+            Backend::Scraper => Ok(Box::new(ScraperBackend::with_client(totem))),
+            Backend::Graph => {
+                let token = self.access_token.as_deref()
+                    .ok_or_else(|| anyhow::anyhow!("--access-token is required with --backend graph"))?;
+                if self.base_url != DEFAULT_BASE_URL {
+                    anyhow::bail!("--base-url only applies to --backend scraper; the graph backend always calls the Graph API");
+                }
+                let backend = GraphApiBackend::new(token).client(self.graph_client()?);
+                match totem.rate_limiter() {
+                    Some(limiter) => Ok(Box::new(backend.rate_limiter(limiter.clone()))),
+                    None => Ok(Box::new(backend)),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Backend {
+    /// Scrape the Ad Library website
+    Scraper,
+    /// Official Ad Library API (ads_archive)
+    Graph,
 }
 
 #[derive(Subcommand)]
@@ -21,105 +269,525 @@ enum Mode {
     Single {
         #[arg(short, long)]
         url: String,
+        #[command(flatten)]
+        query: QueryArgs,
     },
     Multi {
+        /// CSV, JSON Lines or text file of page URLs, IDs or names, or - for stdin
         #[arg(long)]
         urls: String,
+        /// CSV column or JSON key holding the pages [default: detected]
         #[arg(short, long)]
-        columns: String,
+        columns: Option<String>,
+        /// Number of pages fetched at the same time
+        #[arg(long, default_value_t = NonZeroUsize::MIN)]
+        concurrency: NonZeroUsize,
+        /// Skip targets a previous run finished and retry the ones that failed
+        #[arg(long)]
+        resume: bool,
+        /// Progress file for --resume [default: <OUTPUT_DIR>/<URLS file name>.checkpoint.jsonl]
+        #[arg(long)]
+        checkpoint: Option<String>,
+        /// Write every page's ads to the one --output file, with source_target,
+        /// page_id and username columns
+        #[arg(long)]
+        merge: bool,
+        /// Summary of every target's outcome, as JSON if it ends in .json
+        /// [default: <OUTPUT_DIR>/<URLS file name>.report.csv]
+        #[arg(long)]
+        report: Option<String>,
+        #[command(flatten)]
+        query: QueryArgs,
     },
     Search {
         #[arg(short, long)]
         target: String,
     },
+    Keywords {
+        #[arg(short, long)]
+        query: String,
+        /// Match the query as an exact phrase instead of any of its words
+        #[arg(long)]
+        exact: bool,
+        #[command(flatten)]
+        filters: QueryArgs,
+    },
+}
+
+#[derive(Args)]
+struct QueryArgs {
+    /// Stop after this many ads (per page in multi mode)
+    #[arg(long)]
+    max_ads: Option<usize>,
+    /// Country codes the ads were delivered in, e.g. US,GB [default: all]
+    #[arg(long, value_delimiter = ',')]
+    countries: Vec<String>,
+    /// all, active or inactive
+    #[arg(long, default_value_t = ActiveStatus::All)]
+    active_status: ActiveStatus,
+    /// all, political_and_issue_ads, housing, employment or credit
+    #[arg(long, default_value_t = AdType::All)]
+    ad_type: AdType,
+    /// all, image, video, meme or none
+    #[arg(long, default_value_t = MediaType::All)]
+    media_type: MediaType,
+    /// facebook, instagram, audience_network, messenger, whatsapp or threads [default: all]
+    #[arg(long, value_delimiter = ',')]
+    platforms: Vec<PublisherPlatform>,
+    /// Only ads delivered on or after this date (YYYY-MM-DD)
+    #[arg(long, value_parser = parse_date)]
+    date_from: Option<NaiveDate>,
+    /// Only ads delivered on or before this date (YYYY-MM-DD)
+    #[arg(long, value_parser = parse_date)]
+    date_to: Option<NaiveDate>,
+    /// relevancy_monthly_grouped or total_impressions
+    #[arg(long, default_value_t = SortMode::Relevancy)]
+    sort: SortMode,
+    /// desc or asc
+    #[arg(long, default_value_t = SortDirection::Desc)]
+    sort_direction: SortDirection,
+}
+
+/// A `YYYY-MM-DD` date for `--date-from` and `--date-to`.
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|err| format!("{} (expected YYYY-MM-DD, e.g. 2024-03-01)", err))
+}
+
+impl QueryArgs {
+    fn to_query(&self) -> Result<AdQuery> {
+        let query = AdQuery::new()
+            .countries(self.countries.iter().cloned())
+            .active_status(self.active_status)
+            .ad_type(self.ad_type)
+            .media_type(self.media_type)
+            .publisher_platforms(self.platforms.iter().copied())
+            .delivery_date_range(self.date_from, self.date_to)?
+            .sort(self.sort, self.sort_direction);
+        
+        Ok(match self.max_ads {
+            Some(max_ads) => query.max_ads(max_ads),
+            None => query,
+        })
+    }
+}
+
+fn is_record_format(format: OutputFormat) -> bool {
+    matches!(format, OutputFormat::Csv | OutputFormat::Json | OutputFormat::Jsonl)
+}
+
+/// Formats multi mode's `--merge` can put every page's ads into.
+fn is_mergeable(format: OutputFormat) -> bool {
+    match format {
+        #[cfg(feature = "sqlite")]
+        OutputFormat::Sqlite => true,
+        _ => is_record_format(format),
+    }
+}
+
+/// Flips to `true` on the first Ctrl-C so long pulls can stop and keep what
+/// they have. A second Ctrl-C exits straight away.
+fn watch_interrupts() -> watch::Receiver<bool> {
+    let (tx, rx) = watch::channel(false);
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            eprintln!("Interrupted, saving the ads fetched so far (press Ctrl-C again to quit)");
+            let _ = tx.send(true);
+        }
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(130);
+        }
+    });
+    rx
+}
+
+/// How a streamed write ended.
+struct Written {
+    ads: usize,
+    interrupted: bool,
+}
+
+/// The next ad, or `None` once the stream ends or an interrupt arrives.
+async fn next_ad(
+    ads: &mut BoxStream<'_, Result<FacebookAd>>,
+    interrupted: &mut watch::Receiver<bool>,
+    written: &mut Written,
+) -> Option<Result<FacebookAd>> {
+    tokio::select! {
+        next = ads.next() => next,
+        () = interrupt(interrupted) => {
+            written.interrupted = true;
+            None
+        }
+    }
+}
+
+/// Resolves once an interrupt arrives. If the watcher has gone away (e.g. the
+/// Ctrl-C handler couldn't be installed), no interrupt can come, so it never
+/// resolves.
+async fn interrupt(interrupted: &mut watch::Receiver<bool>) {
+    if interrupted.wait_for(|interrupted| *interrupted).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Write ads to `output_path`, or stdout for `-`, as they are fetched. Record
+/// formats go straight to their sink; SQLite and columnar files are written
+/// once the stream ends. On an interrupt or an error the ads fetched so far
+/// are still written.
+async fn write_ad_stream(
+    mut ads: BoxStream<'_, Result<FacebookAd>>,
+    output_path: &str,
+    format: OutputFormat,
+    options: &OutputOptions,
+    interrupted: &mut watch::Receiver<bool>,
+) -> Result<Written> {
+    let mut sink = if output_path == STDOUT {
+        Some(create_writer_sink(format, std::io::stdout(), options)?)
+    } else if is_record_format(format) {
+        Some(create_sink(format, output_path, options)?)
+    } else {
+        None
+    };
+    let mut buffered = Vec::new();
+    let mut written = Written { ads: 0, interrupted: false };
+    let mut failure = None;
+    
+    loop {
+        match next_ad(&mut ads, interrupted, &mut written).await {
+            Some(Ok(ad)) => {
+                match &mut sink {
+                    Some(sink) => {
+                        // Opened on the first ad so that no results leave no file.
+                        if written.ads == 0 {
+                            sink.open()?;
+                        }
+                        sink.write_record(&ad.raw)?;
+                    }
+                    None => buffered.push(ad),
+                }
+                written.ads += 1;
+            }
+            Some(Err(err)) => {
+                failure = Some(err);
+                break;
+            }
+            None => break,
+        }
+    }
+    
+    if written.ads > 0 {
+        match &mut sink {
+            Some(sink) => sink.finish()?,
+            None => write_ads(&buffered, output_path, format, options)?,
+        }
+    }
+    match failure {
+        Some(err) if written.ads > 0 && output_path == STDOUT => Err(err.context(format!("stopped after {} ads", written.ads))),
+        Some(err) if written.ads > 0 => Err(err.context(format!("stopped after {} ads, which were saved to {}", written.ads, output_path))),
+        Some(err) => Err(err),
+        None => Ok(written),
+    }
+}
+
+/// Every page's ads in one sink, for multi mode's `--merge`.
+struct MergedSink {
+    sink: Box<dyn OutputSink>,
+    opened: bool,
+}
+
+/// The target a page was found through.
+struct Source<'a> {
+    target: &'a str,
+    page_id: &'a str,
+    username: &'a str,
+}
+
+/// `ad`'s raw object with `source_target`, `page_id` and `username` added.
+fn with_source(ad: &FacebookAd, source: &Source) -> Value {
+    let mut record = ad.raw.clone();
+    if let Value::Object(fields) = &mut record {
+        fields.insert("source_target".to_string(), source.target.into());
+        fields.insert("page_id".to_string(), source.page_id.into());
+        let username = (!source.username.is_empty()).then(|| source.username.into());
+        fields.insert("username".to_string(), username.unwrap_or(Value::Null));
+    }
+    record
+}
+
+/// Write a page's ads into the merged sink, opening it on the first ad of any
+/// page. The sink is finished once every page is done.
+async fn write_merged_ad_stream(
+    mut ads: BoxStream<'_, Result<FacebookAd>>,
+    merged: &RefCell<MergedSink>,
+    source: &Source<'_>,
+    interrupted: &mut watch::Receiver<bool>,
+) -> Result<Written> {
+    let mut written = Written { ads: 0, interrupted: false };
+    while let Some(ad) = next_ad(&mut ads, interrupted, &mut written).await {
+        let ad = match ad {
+            Ok(ad) => ad,
+            Err(err) if written.ads > 0 => return Err(err.context(format!("stopped after {} ads", written.ads))),
+            Err(err) => return Err(err),
+        };
+        let mut merged = merged.borrow_mut();
+        if !merged.opened {
+            merged.sink.open()?;
+            merged.opened = true;
+        }
+        merged.sink.write_record(&with_source(&ad, source))?;
+        written.ads += 1;
+    }
+    Ok(written)
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    let totem = Arc::new(cli.build_totem()?);
+    let backend = cli.build_backend(totem.clone())?;
+    let output_options = cli.output_options();
+    let format = OutputFormat::from(cli.format);
+    let ext = format.extension();
     
-    if !Path::new("output").exists() {
-        std::fs::create_dir("output")?;
+    if cli.writes_to_stdout() {
+        if !is_record_format(format) {
+            anyhow::bail!("{} output can't be written to stdout", ext);
+        }
+        STATUS_TO_STDERR.store(true, Ordering::Relaxed);
     }
     
-    match cli.mode {
-        Mode::Single { url } => {
-            println!("Getting page ID from URL...");
-            let id = get_id_from_url(&url).await?;
-            println!("Getting ads for page ID: {}", id);
-            let result = get_ads_from_id(&id).await?;
+    match &cli.mode {
+        Mode::Single { url, query } => {
+            let query = query.to_query()?;
+            status!("Getting page ID from URL...");
+            let id = totem.get_id_from_url(url).await?;
+            status!("Getting ads for page ID: {}", id);
+            let username = Target::Url(url.clone()).username();
+            let output_path = cli.output_path(&FilenameValues::new("single", ext).username(&username).page_id(&id))?;
+            let mut interrupted = watch_interrupts();
+            let written = write_ad_stream(backend.ads_stream(&id, &query), &output_path, format, &output_options, &mut interrupted).await?;
             
-            if !result.is_empty() {
-                let output_path = format!("output/{}", cli.output);
-                write_json_to_csv(&result, &output_path)?;
-                println!("You can see the output in: {}", output_path);
-            } else {
-                println!("Sorry, but this page hasn't used any ads");
+            if written.ads > 0 {
+                if output_path != STDOUT {
+                    status!("You can see the output in: {}", output_path);
+                }
+            } else if !written.interrupted {
+                status!("Sorry, but this page hasn't used any ads");
             }
         }
-        Mode::Multi { urls, columns } => {
-            let mut targets = Vec::new();
-            let mut rdr = Reader::from_path(&urls)?;
-            
-            for result in rdr.deserialize() {
-                let record: HashMap<String, String> = result?;
-                if let Some(url) = record.get(&columns) {
-                    targets.push(url.clone());
+        Mode::Multi { urls, columns, concurrency, resume, checkpoint, merge, report, query } => {
+            if cli.writes_to_stdout() && !merge && format != OutputFormat::Jsonl {
+                anyhow::bail!("multi mode can only write jsonl to stdout, or any of csv, json and jsonl with --merge");
+            }
+            if *merge && !is_mergeable(format) {
+                anyhow::bail!("--merge can't write {} output", ext);
+            }
+            // The merged file is written from scratch, so resuming into it
+            // would lose what the earlier run wrote.
+            if *merge && *resume && is_record_format(format) && !cli.writes_to_stdout() {
+                anyhow::bail!("--merge --resume would overwrite the {} file from the earlier run; merge into sqlite to resume", ext);
+            }
+            // Checked up front, so a bad template fails the run before any page
+            // is fetched instead of failing every target.
+            if !*merge && !cli.writes_to_stdout() && let Some(template) = &cli.filename_template {
+                if !template.names_pages() {
+                    anyhow::bail!("--filename-template needs {{page_id}} or {{username}} in multi mode, or every page would overwrite the same file");
                 }
+                template.render(&FilenameValues::new("multi", ext).username("").page_id(""))?;
             }
+            let query = query.to_query()?;
+            let mut targets = read_targets(urls, columns.as_deref())?;
+            
+            status!("{} targets found", targets.len());
             
-            println!("{} targets found", targets.len());
+            let urls_name = match urls.as_str() {
+                "-" => "stdin".into(),
+                path => Path::new(path).file_stem().unwrap_or_default().to_string_lossy(),
+            };
+            let checkpoint_path = match checkpoint {
+                Some(path) => path.clone(),
+                None => cli.in_output_dir(&format!("{}.checkpoint.jsonl", urls_name))?,
+            };
+            let report_path = match report {
+                Some(path) => path.clone(),
+                None => cli.in_output_dir(&format!("{}.report.csv", urls_name))?,
+            };
+            let all_targets: Vec<String> = targets.iter().map(Target::to_string).collect();
+            let mut checkpoint = if *resume {
+                let checkpoint = Checkpoint::resume(&checkpoint_path)?;
+                targets.retain(|target| !checkpoint.is_complete(&target.to_string()));
+                status!("Resuming from {}: {} targets already done", checkpoint_path, all_targets.len() - targets.len());
+                checkpoint
+            } else {
+                Checkpoint::create(&checkpoint_path)?
+            };
+            
+            // With --merge, record formats share one sink, and SQLite output
+            // goes to one database as usual with each target recorded.
+            let merged_path = if *merge {
+                Some(cli.output_path(&FilenameValues::new("multi", ext))?)
+            } else {
+                None
+            };
+            let merged_sink = match &merged_path {
+                Some(path) if path == STDOUT => {
+                    Some(RefCell::new(MergedSink { sink: create_writer_sink(format, std::io::stdout(), &output_options)?, opened: false }))
+                }
+                Some(path) if is_record_format(format) => {
+                    Some(RefCell::new(MergedSink { sink: create_sink(format, path, &output_options)?, opened: false }))
+                }
+                _ => None,
+            };
+            #[cfg(feature = "sqlite")]
+            let mut target_store = match &merged_path {
+                Some(path) if format == OutputFormat::Sqlite => Some(SqliteStore::open(path)?),
+                _ => None,
+            };
             
+            let interrupted = watch_interrupts();
             let pb = ProgressBar::new(targets.len() as u64);
             pb.set_style(ProgressStyle::default_bar()
                 .template("[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}")?
                 .progress_chars("##-"));
             
-            for target in targets {
-                pb.set_message(format!("Processing: {}", target));
-                
-                let username = target.split(".com/").nth(1)
-                    .unwrap_or("")
-                    .replace('/', "");
-                
-                match get_id_from_url(&target).await {
-                    Ok(id) => {
-                        match get_ads_from_id(&id).await {
-                            Ok(result) => {
-                                if !result.is_empty() {
-                                    let output_path = format!("output/{}{}.csv", username, id);
-                                    let _ = write_json_to_csv(&result, &output_path);
+            let (cli, totem, backend, query, output_options) = (&cli, &totem, &backend, &query, &output_options);
+            let (merged_path, merged_sink) = (&merged_path, &merged_sink);
+            let mut finished = stream::iter(targets)
+                .map(|target| {
+                    let mut interrupted = interrupted.clone();
+                    async move {
+                        // Targets not started before an interrupt are left for --resume.
+                        if *interrupted.borrow() {
+                            return Ok(None);
+                        }
+                        let username = target.username();
+                        let mut entry = CheckpointEntry::new(&target.to_string());
+                        
+                        match target.page_id(totem).await {
+                            Ok(id) => {
+                                let ads = backend.ads_stream(&id, query);
+                                let written = match (merged_sink, merged_path) {
+                                    (Some(merged), _) => {
+                                        let source = Source { target: &entry.target, page_id: &id, username: &username };
+                                        write_merged_ad_stream(ads, merged, &source, &mut interrupted).await
+                                    }
+                                    (None, Some(path)) => write_ad_stream(ads, path, format, output_options, &mut interrupted).await,
+                                    // A path that can't be made fails this target, not the run.
+                                    (None, None) => async {
+                                        let values = FilenameValues::new("multi", ext).username(&username).page_id(&id);
+                                        let output_path = cli.multi_output_path(format, &values)?;
+                                        write_ad_stream(ads, &output_path, format, output_options, &mut interrupted).await
+                                    }
+                                    .await,
+                                };
+                                entry.page_id = Some(id);
+                                match written {
+                                    // A page cut short by an interrupt is fetched again on --resume.
+                                    Ok(written) if written.interrupted => return Ok(None),
+                                    Ok(written) if written.ads > 0 => {
+                                        entry.status = TargetStatus::Done;
+                                        entry.ads = written.ads;
+                                    }
+                                    Ok(_) => entry.status = TargetStatus::NoAds,
+                                    Err(err) => {
+                                        entry.status = TargetStatus::from_error(&err);
+                                        entry.error = Some(format!("{:#}", err));
+                                    }
                                 }
                             }
-                            Err(_) => {
-                                // Skip failed requests
+                            Err(err) => {
+                                entry.status = match TargetStatus::from_error(&err) {
+                                    TargetStatus::Failed => TargetStatus::IdNotFound,
+                                    status => status,
+                                };
+                                entry.error = Some(format!("{:#}", err));
                             }
                         }
+                        anyhow::Ok(Some(entry))
                     }
-                    Err(_) => {
-                        // Skip failed requests
+                })
+                .buffer_unordered(concurrency.get());
+            
+            while let Some(entry) = finished.next().await {
+                if let Some(entry) = entry? {
+                    #[cfg(feature = "sqlite")]
+                    if let Some(store) = &mut target_store
+                        && let Some(page_id) = &entry.page_id
+                    {
+                        let username = Target::parse(&entry.target).map(|target| target.username()).unwrap_or_default();
+                        store.upsert_target(&entry.target, page_id, Some(username.as_str()).filter(|username| !username.is_empty()))?;
                     }
+                    checkpoint.record(&entry)?;
+                    pb.set_message(format!("Finished: {}", entry.target));
+                    pb.inc(1);
                 }
-                
-                pb.inc(1);
             }
             
             pb.finish_with_message("Processing complete");
-            println!("You can see the results in the output folder. Pages that used ads have a file in the output folder.");
+            if let Some(merged) = merged_sink {
+                let mut merged = merged.borrow_mut();
+                if merged.opened {
+                    merged.sink.finish()?;
+                }
+            }
+            
+            // Every target is reported, including ones finished by an earlier
+            // run and ones an interrupt kept from starting.
+            let entries: Vec<CheckpointEntry> = all_targets.iter()
+                .map(|target| checkpoint.entry(target).cloned().unwrap_or_else(|| CheckpointEntry::new(target)))
+                .collect();
+            write_report(&entries, &report_path)?;
+            
+            let count = |status: TargetStatus| entries.iter().filter(|entry| entry.status == status).count();
+            let failed = entries.iter().filter(|entry| entry.status.is_failure()).count();
+            status!(
+                "{} with ads, {} without ads, {} failed, {} skipped",
+                count(TargetStatus::Done), count(TargetStatus::NoAds), failed, count(TargetStatus::Skipped)
+            );
+            match merged_path {
+                Some(path) if path != STDOUT => status!("You can see the results in: {}", path),
+                Some(_) => {}
+                None if cli.writes_to_stdout() => {}
+                None => status!("You can see the results in {}. Pages that used ads have a file there.", cli.output_dir),
+            }
+            status!("Outcome of every target: {}", report_path);
+            if failed > 0 {
+                anyhow::bail!("{} of {} targets failed, see {}", failed, entries.len(), report_path);
+            }
         }
         Mode::Search { target } => {
-            println!("Searching for pages with name: {}", target);
-            let result = get_facebook_page_from_name(&target).await?;
+            status!("Searching for pages with name: {}", target);
+            let result = totem.get_facebook_page_from_name(target).await?;
             
-            if !result.is_empty() {
-                let output_path = format!("output/{}", cli.output);
-                write_facebook_pages_to_csv(&result, &output_path)?;
-                println!("You can see the output in: {}", output_path);
+            if result.is_empty() {
+                status!("Sorry, no pages found with this name");
+            } else if cli.writes_to_stdout() {
+                let mut sink = create_writer_sink(format, std::io::stdout(), &OutputOptions::default())?;
+                write_facebook_pages_to_sink(&result, &mut sink)?;
             } else {
-                println!("Sorry, no pages found with this name");
+                let output_path = cli.output_path(&FilenameValues::new("search", ext).query(target))?;
+                write_facebook_pages(&result, &output_path, format)?;
+                status!("You can see the output in: {}", output_path);
+            }
+        }
+        Mode::Keywords { query, exact, filters } => {
+            let keyword_match = if *exact { KeywordMatch::ExactPhrase } else { KeywordMatch::AnyWord };
+            status!("Searching for ads matching: {}", query);
+            let output_path = cli.output_path(&FilenameValues::new("keywords", ext).query(query))?;
+            let mut interrupted = watch_interrupts();
+            let filters = filters.to_query()?;
+            let ads = backend.keywords_stream(query, keyword_match, &filters);
+            let written = write_ad_stream(ads, &output_path, format, &output_options, &mut interrupted).await?;
+            
+            if written.ads > 0 {
+                if output_path != STDOUT {
+                    status!("You can see the output in: {}", output_path);
+                }
+            } else if !written.interrupted {
+                status!("Sorry, no ads found matching this query");
             }
         }
     }
     
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,150 @@
+/// Where in a page's HTML a page ID was found, from most to least reliable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PageIdSource {
+    /// The `al:android:url` app link meta tag, e.g. `fb://page/123`.
+    AndroidAppLink,
+    /// Any other `fb://page/` app link in the page.
+    AppLink,
+    /// A `delegate_page` object or `delegate_page_id` key, which new-style
+    /// pages use to point at the page behind a profile.
+    DelegatePage,
+    /// A `"pageID"` key in the page's embedded JSON.
+    PageId,
+    /// A `"page_id"` key in the page's embedded JSON.
+    PageIdSnakeCase,
+    /// A `"userID"` key, which on pages run as profiles is the page's ID. It
+    /// is also the logged-in viewer's ID, so it is only ever a candidate.
+    UserId,
+}
+
+impl PageIdSource {
+    /// Whether an ID from here is taken as the page's own. `"page_id"` also
+    /// turns up for suggested and related pages, and `"userID"` is the
+    /// viewer's own ID when logged in.
+    pub fn is_reliable(self) -> bool {
+        matches!(self, PageIdSource::AndroidAppLink | PageIdSource::AppLink | PageIdSource::DelegatePage | PageIdSource::PageId)
+    }
+}
+
+/// A page ID found in HTML, with the pattern that matched it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageIdCandidate {
+    pub page_id: String,
+    pub source: PageIdSource,
+}
+
+/// Every page ID in `html`, most reliable first. An ID found by several
+/// patterns is listed once, under the most reliable of them; within a
+/// pattern, IDs keep the order they appear in.
+///
+/// ```
+/// use facebook_totem::{page_id_candidates, PageIdSource};
+///
+/// let html = r#"<meta property="al:android:url" content="fb://page/123456789" />
+///     <script>{"userID":"42","pageID":"123456789"}</script>"#;
+/// let candidates = page_id_candidates(html);
+///
+/// assert_eq!(candidates[0].page_id, "123456789");
+/// assert_eq!(candidates[0].source, PageIdSource::AndroidAppLink);
+/// assert_eq!(candidates[1].source, PageIdSource::UserId);
+/// ```
+pub fn page_id_candidates(html: &str) -> Vec<PageIdCandidate> {
+    let mut candidates: Vec<PageIdCandidate> = Vec::new();
+    let mut add = |source: PageIdSource, ids: Vec<String>| {
+        for page_id in ids {
+            if !candidates.iter().any(|candidate| candidate.page_id == page_id) {
+                candidates.push(PageIdCandidate { page_id, source });
+            }
+        }
+    };
+
+    add(PageIdSource::AndroidAppLink, android_app_link_ids(html));
+    add(PageIdSource::AppLink, ids_after_any(html, &["fb://page/", "fb:\\/\\/page\\/"]));
+    add(PageIdSource::DelegatePage, ids_after_any(html, &["\"delegate_page\":{\"id\":", "\"delegate_page_id\":"]));
+    add(PageIdSource::PageId, ids_after_any(html, &["\"pageID\":"]));
+    add(PageIdSource::PageIdSnakeCase, ids_after_any(html, &["\"page_id\":"]));
+    add(PageIdSource::UserId, ids_after_any(html, &["\"userID\":"]));
+    candidates
+}
+
+/// IDs in the `content` of `al:android:url` meta tags, whichever order the
+/// attributes come in.
+fn android_app_link_ids(html: &str) -> Vec<String> {
+    html.split("<meta")
+        .skip(1)
+        .filter_map(|tag| tag.split('>').next())
+        .filter(|tag| tag.contains("\"al:android:url\""))
+        .filter_map(|tag| {
+            let start = tag.find("content=\"")? + "content=\"".len();
+            let content = &tag[start..start + tag[start..].find('"')?];
+            let link = content.strip_prefix("fb://page/").or_else(|| content.strip_prefix("fb://profile/"))?;
+            page_id_at(link)
+        })
+        .collect()
+}
+
+fn ids_after_any(html: &str, markers: &[&str]) -> Vec<String> {
+    markers
+        .iter()
+        .flat_map(|marker| html.match_indices(marker).filter_map(|(index, _)| page_id_at(&html[index + marker.len()..])))
+        .collect()
+}
+
+/// The ID at the start of `text`, quoted or not and optionally behind an
+/// `?id=` query. `0`, which logged-out pages use for the viewer, isn't an ID.
+fn page_id_at(text: &str) -> Option<String> {
+    let text = text.trim_start();
+    let text = text.strip_prefix('"').unwrap_or(text);
+    let text = text.strip_prefix("?id=").unwrap_or(text);
+    let id: String = text.chars().take_while(|c| c.is_ascii_digit()).collect();
+    (!id.trim_start_matches('0').is_empty()).then_some(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn best(html: &str) -> Option<(String, PageIdSource)> {
+        page_id_candidates(html).into_iter().next().map(|candidate| (candidate.page_id, candidate.source))
+    }
+
+    #[test]
+    fn test_each_layout() {
+        let fixtures = [
+            (r#"<meta property="al:android:url" content="fb://page/111111111" />"#, PageIdSource::AndroidAppLink),
+            (r#"<meta content="fb://profile/111111111" property="al:android:url">"#, PageIdSource::AndroidAppLink),
+            (r#"<meta property="al:ios:url" content="fb://page/?id=111111111" />"#, PageIdSource::AppLink),
+            (r#"<script>{"uri":"fb:\/\/page\/111111111"}</script>"#, PageIdSource::AppLink),
+            (r#"<script>{"delegate_page":{"id":"111111111","is_business_page_active":true}}</script>"#, PageIdSource::DelegatePage),
+            (r#"<script>{"delegate_page_id":111111111}</script>"#, PageIdSource::DelegatePage),
+            (r#"<html>some content[{"pageID":"111111111","other":"data"}]more content</html>"#, PageIdSource::PageId),
+            (r#"<script>{"page_id": "111111111"}</script>"#, PageIdSource::PageIdSnakeCase),
+            (r#"<script>{"USER_ID":"0","userID":"111111111"}</script>"#, PageIdSource::UserId),
+        ];
+
+        for (html, source) in fixtures {
+            assert_eq!(best(html), Some(("111111111".to_string(), source)), "{}", html);
+        }
+    }
+
+    #[test]
+    fn test_most_reliable_pattern_wins() {
+        let html = r#"<meta property="al:android:url" content="fb://page/222" />
+            <script>{"userID":"333","pageID":"444","page_id":"222"}</script>"#;
+
+        let candidates = page_id_candidates(html);
+
+        assert_eq!(candidates, vec![
+            PageIdCandidate { page_id: "222".to_string(), source: PageIdSource::AndroidAppLink },
+            PageIdCandidate { page_id: "444".to_string(), source: PageIdSource::PageId },
+            PageIdCandidate { page_id: "333".to_string(), source: PageIdSource::UserId },
+        ]);
+    }
+
+    #[test]
+    fn test_no_ids() {
+        assert!(page_id_candidates("<html>no page id here</html>").is_empty());
+        assert!(page_id_candidates(r#"[{"pageID":""#).is_empty());
+        assert!(page_id_candidates(r#"{"userID":"0","pageID":null}"#).is_empty());
+    }
+}
@@ -0,0 +1,175 @@
+/// First path segments that are Facebook sections rather than page names.
+const RESERVED_PATHS: &[&str] = &[
+    "ads", "events", "gaming", "groups", "hashtag", "help", "home.php", "login", "login.php", "marketplace", "permalink.php",
+    "photo", "photo.php", "policies", "settings", "share", "sharer.php", "story.php", "watch",
+];
+
+/// What a page URL says about the page, worked out without fetching it.
+///
+/// Handles `profile.php?id=`, `/pages/<name>/<id>`, `/people/<name>/<id>`,
+/// `/p/<name>-<id>`, `/pg/<username>`, vanity names with or without a
+/// numeric suffix, `m.`/`mbasic.`/`web.` hosts, query strings, fragments and
+/// trailing slashes, as well as bare numeric IDs. URLs on any host other than
+/// `facebook.com` or `fb.com` aren't pages.
+///
+/// ```
+/// use facebook_totem::PageRef;
+///
+/// let page = PageRef::parse("https://m.facebook.com/profile.php?id=100064&ref=share").unwrap();
+/// assert_eq!(page.page_id.as_deref(), Some("100064"));
+///
+/// let page = PageRef::parse("facebook.com/somepage/?ref=bookmarks").unwrap();
+/// assert_eq!(page.username.as_deref(), Some("somepage"));
+/// assert_eq!(page.page_id, None);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PageRef {
+    /// The page ID, when the URL contains it.
+    pub page_id: Option<String>,
+    /// The vanity name or name slug from the URL.
+    pub username: Option<String>,
+}
+
+impl PageRef {
+    /// Parse a page URL or a bare page ID. Returns `None` for anything that
+    /// doesn't point at a page, such as a group, a post or a plain name.
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        if is_page_id(input) {
+            return Some(PageRef { page_id: Some(input.to_string()), username: None });
+        }
+
+        let without_scheme = match input.split_once("://") {
+            Some((_, rest)) => rest,
+            None if input.contains("facebook.com") || input.starts_with("fb.com") => input,
+            None => return None,
+        };
+        let location = without_scheme.split('#').next().unwrap_or_default();
+        let (location, query) = location.split_once('?').unwrap_or((location, ""));
+        let mut segments = location.split('/');
+        if !is_facebook_host(segments.next()?) {
+            return None;
+        }
+        let mut segments = segments.filter(|segment| !segment.is_empty());
+
+        let page = match segments.next()? {
+            "profile.php" => PageRef { page_id: query_param(query, "id").filter(|id| is_page_id(id)).map(str::to_string), username: None },
+            "pages" | "people" => {
+                let rest: Vec<&str> = segments.collect();
+                let page_id = rest.iter().rev().find_map(|segment| id_in_segment(segment));
+                let username = rest.iter().rev().find(|segment| !is_page_id(segment)).map(|name| name.to_string());
+                PageRef { page_id, username }
+            }
+            "p" => {
+                let slug = segments.next()?;
+                PageRef { page_id: id_in_segment(slug), username: Some(slug.to_string()) }
+            }
+            "pg" => PageRef { page_id: None, username: Some(segments.next()?.to_string()) }.with_id_from_username(),
+            first if RESERVED_PATHS.contains(&first.to_lowercase().as_str()) => return None,
+            vanity if is_page_id(vanity) => PageRef { page_id: Some(vanity.to_string()), username: None },
+            vanity => PageRef { page_id: None, username: Some(vanity.to_string()) }.with_id_from_username(),
+        };
+        (page.page_id.is_some() || page.username.is_some()).then_some(page)
+    }
+
+    /// Old-style vanity names end with the page ID, e.g. `Some-Page-123456789`.
+    fn with_id_from_username(mut self) -> Self {
+        self.page_id = self.username.as_deref().and_then(id_suffix);
+        self
+    }
+}
+
+/// `facebook.com` or `fb.com`, bare or on one of the subdomains pages are
+/// served from. A port is ignored.
+fn is_facebook_host(host: &str) -> bool {
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    let host = host.split_once(':').map_or(host, |(host, _)| host).to_lowercase();
+    let domain = ["www.", "m.", "mbasic.", "web."]
+        .iter()
+        .find_map(|subdomain| host.strip_prefix(subdomain))
+        .unwrap_or(&host);
+    domain == "facebook.com" || domain == "fb.com"
+}
+
+fn is_page_id(value: &str) -> bool {
+    !value.is_empty() && value.chars().all(|c| c.is_ascii_digit())
+}
+
+/// A segment that is an ID, or a slug ending in one.
+fn id_in_segment(segment: &str) -> Option<String> {
+    if is_page_id(segment) {
+        Some(segment.to_string())
+    } else {
+        id_suffix(segment)
+    }
+}
+
+/// The `123456789` in `Some-Page-123456789`. Shorter numbers are more likely
+/// part of the name than an ID.
+fn id_suffix(slug: &str) -> Option<String> {
+    let (_, suffix) = slug.rsplit_once('-')?;
+    (is_page_id(suffix) && suffix.len() >= 9).then(|| suffix.to_string())
+}
+
+fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query.split('&').find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> (Option<String>, Option<String>) {
+        let page = PageRef::parse(input).unwrap_or_else(|| panic!("{} didn't parse", input));
+        (page.page_id, page.username)
+    }
+
+    fn some(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+
+    #[test]
+    fn test_parse_ids_in_urls() {
+        assert_eq!(parse("123456"), (some("123456"), None));
+        assert_eq!(parse("https://www.facebook.com/profile.php?id=100064123456789"), (some("100064123456789"), None));
+        assert_eq!(parse("https://m.facebook.com/profile.php?ref=bookmarks&id=42&sk=about"), (some("42"), None));
+        assert_eq!(parse("https://www.facebook.com/pages/Some-Page/123456789012/"), (some("123456789012"), some("Some-Page")));
+        assert_eq!(
+            parse("https://www.facebook.com/pages/category/Local-Business/Some-Shop-123456789012/"),
+            (some("123456789012"), some("Some-Shop-123456789012"))
+        );
+        assert_eq!(parse("https://www.facebook.com/people/Jane-Doe/100012345678901/"), (some("100012345678901"), some("Jane-Doe")));
+        assert_eq!(parse("https://web.facebook.com/p/Some-Page-100064123456789/"), (some("100064123456789"), some("Some-Page-100064123456789")));
+        assert_eq!(parse("https://www.facebook.com/Some-Page-123456789012"), (some("123456789012"), some("Some-Page-123456789012")));
+        assert_eq!(parse("https://mbasic.facebook.com/123456789"), (some("123456789"), None));
+    }
+
+    #[test]
+    fn test_parse_vanity_names() {
+        assert_eq!(parse("https://www.facebook.com/somepage"), (None, some("somepage")));
+        assert_eq!(parse("https://m.facebook.com/somepage/?ref=page_internal#posts"), (None, some("somepage")));
+        assert_eq!(parse("facebook.com/somepage/posts/123456789012"), (None, some("somepage")));
+        assert_eq!(parse("http://www.facebook.com/pg/somepage/ads/"), (None, some("somepage")));
+        assert_eq!(parse("https://www.facebook.com/store-2024"), (None, some("store-2024")));
+    }
+
+    #[test]
+    fn test_parse_non_pages() {
+        assert_eq!(PageRef::parse("Some Page"), None);
+        assert_eq!(PageRef::parse("https://www.facebook.com/"), None);
+        assert_eq!(PageRef::parse("https://www.facebook.com/groups/123456789"), None);
+        assert_eq!(PageRef::parse("https://www.facebook.com/watch/?v=123456789"), None);
+        assert_eq!(PageRef::parse("https://www.facebook.com/profile.php"), None);
+    }
+
+    #[test]
+    fn test_parse_other_hosts() {
+        assert_eq!(PageRef::parse("https://example.com/123456789012"), None);
+        assert_eq!(PageRef::parse("https://evil.test/profile.php?id=42"), None);
+        assert_eq!(PageRef::parse("https://facebook.com.evil.test/somepage"), None);
+        assert_eq!(PageRef::parse("https://notfacebook.com/somepage"), None);
+        assert_eq!(PageRef::parse("https://www.facebook.com@evil.test/somepage"), None);
+        assert_eq!(parse("https://WWW.Facebook.com:443/somepage"), (None, some("somepage")));
+        assert_eq!(parse("https://fb.com/profile.php?id=42"), (some("42"), None));
+    }
+}
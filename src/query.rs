@@ -0,0 +1,362 @@
+use anyhow::{Result, anyhow};
+use chrono::NaiveDate;
+use std::fmt;
+use std::str::FromStr;
+
+/// Whether to return ads that are currently running, stopped, or both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ActiveStatus {
+    #[default]
+    All,
+    Active,
+    Inactive,
+}
+
+/// Ad category, as filtered by the Ad Library's "Ad category" selector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AdType {
+    #[default]
+    All,
+    PoliticalAndIssueAds,
+    Housing,
+    Employment,
+    Credit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MediaType {
+    #[default]
+    All,
+    Image,
+    Video,
+    Meme,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublisherPlatform {
+    Facebook,
+    Instagram,
+    AudienceNetwork,
+    Messenger,
+    WhatsApp,
+    Threads,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortMode {
+    #[default]
+    Relevancy,
+    TotalImpressions,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortDirection {
+    #[default]
+    Desc,
+    Asc,
+}
+
+macro_rules! param_enum {
+    ($ty:ident { $($variant:ident => $param:literal),+ $(,)? }) => {
+        impl $ty {
+            pub fn as_param(self) -> &'static str {
+                match self {
+                    $($ty::$variant => $param,)+
+                }
+            }
+        }
+
+        impl fmt::Display for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.as_param())
+            }
+        }
+
+        impl FromStr for $ty {
+            type Err = anyhow::Error;
+
+            fn from_str(s: &str) -> Result<Self> {
+                let normalized = s.trim().to_ascii_lowercase().replace('-', "_");
+                match normalized.as_str() {
+                    $($param => Ok($ty::$variant),)+
+                    _ => Err(anyhow!(
+                        "invalid value '{}', expected one of: {}",
+                        s,
+                        [$($param),+].join(", ")
+                    )),
+                }
+            }
+        }
+    };
+}
+
+param_enum!(ActiveStatus {
+    All => "all",
+    Active => "active",
+    Inactive => "inactive",
+});
+
+param_enum!(AdType {
+    All => "all",
+    PoliticalAndIssueAds => "political_and_issue_ads",
+    Housing => "housing",
+    Employment => "employment",
+    Credit => "credit",
+});
+
+param_enum!(MediaType {
+    All => "all",
+    Image => "image",
+    Video => "video",
+    Meme => "meme",
+    None => "none",
+});
+
+param_enum!(PublisherPlatform {
+    Facebook => "facebook",
+    Instagram => "instagram",
+    AudienceNetwork => "audience_network",
+    Messenger => "messenger",
+    WhatsApp => "whatsapp",
+    Threads => "threads",
+});
+
+param_enum!(SortMode {
+    Relevancy => "relevancy_monthly_grouped",
+    TotalImpressions => "total_impressions",
+});
+
+param_enum!(SortDirection {
+    Desc => "desc",
+    Asc => "asc",
+});
+
+/// Filters and limits for an Ad Library search.
+///
+/// The default query matches what the Ad Library shows with no filters set:
+/// all countries, active and inactive ads of every type, most relevant first.
+///
+/// ```
+/// use chrono::NaiveDate;
+/// use facebook_totem::{AdQuery, AdType, ActiveStatus};
+///
+/// let query = AdQuery::new()
+///     .countries(["US", "GB"])
+///     .active_status(ActiveStatus::Active)
+///     .ad_type(AdType::PoliticalAndIssueAds)
+///     .delivery_date_range(NaiveDate::from_ymd_opt(2024, 1, 1), None)?
+///     .max_ads(100);
+/// assert_eq!(query.get_max_ads(), Some(100));
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct AdQuery {
+    countries: Vec<String>,
+    active_status: ActiveStatus,
+    ad_type: AdType,
+    media_type: MediaType,
+    publisher_platforms: Vec<PublisherPlatform>,
+    delivery_date_min: Option<NaiveDate>,
+    delivery_date_max: Option<NaiveDate>,
+    sort_mode: SortMode,
+    sort_direction: SortDirection,
+    max_ads: Option<usize>,
+}
+
+impl AdQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restrict to ads delivered in these ISO country codes. Empty means all countries.
+    pub fn countries<I, S>(mut self, countries: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.countries = countries
+            .into_iter()
+            .map(|c| c.into().trim().to_ascii_uppercase())
+            .filter(|c| !c.is_empty())
+            .collect();
+        self
+    }
+
+    pub fn active_status(mut self, active_status: ActiveStatus) -> Self {
+        self.active_status = active_status;
+        self
+    }
+
+    pub fn ad_type(mut self, ad_type: AdType) -> Self {
+        self.ad_type = ad_type;
+        self
+    }
+
+    pub fn media_type(mut self, media_type: MediaType) -> Self {
+        self.media_type = media_type;
+        self
+    }
+
+    /// Restrict to ads shown on these platforms. Empty means all platforms.
+    pub fn publisher_platforms<I>(mut self, platforms: I) -> Self
+    where
+        I: IntoIterator<Item = PublisherPlatform>,
+    {
+        self.publisher_platforms = platforms.into_iter().collect();
+        self
+    }
+
+    /// Only return ads delivered between these dates, inclusive. A `min`
+    /// after `max` is an error, as Facebook would just return nothing.
+    pub fn delivery_date_range(mut self, min: Option<NaiveDate>, max: Option<NaiveDate>) -> Result<Self> {
+        if let (Some(min), Some(max)) = (min, max)
+            && min > max
+        {
+            return Err(anyhow!("the delivery date range starts on {} after it ends on {}", min, max));
+        }
+        self.delivery_date_min = min;
+        self.delivery_date_max = max;
+        Ok(self)
+    }
+
+    pub fn sort(mut self, mode: SortMode, direction: SortDirection) -> Self {
+        self.sort_mode = mode;
+        self.sort_direction = direction;
+        self
+    }
+
+    /// Stop paginating once this many ads have been collected.
+    pub fn max_ads(mut self, max_ads: usize) -> Self {
+        self.max_ads = Some(max_ads);
+        self
+    }
+
+    pub fn get_max_ads(&self) -> Option<usize> {
+        self.max_ads
+    }
+
+    pub fn get_countries(&self) -> &[String] {
+        &self.countries
+    }
+
+    pub fn get_active_status(&self) -> ActiveStatus {
+        self.active_status
+    }
+
+    pub fn get_ad_type(&self) -> AdType {
+        self.ad_type
+    }
+
+    pub fn get_media_type(&self) -> MediaType {
+        self.media_type
+    }
+
+    pub fn get_publisher_platforms(&self) -> &[PublisherPlatform] {
+        &self.publisher_platforms
+    }
+
+    pub fn get_delivery_date_range(&self) -> (Option<NaiveDate>, Option<NaiveDate>) {
+        (self.delivery_date_min, self.delivery_date_max)
+    }
+
+    pub fn get_sort(&self) -> (SortMode, SortDirection) {
+        (self.sort_mode, self.sort_direction)
+    }
+
+    /// Query-string parameters for the `search_ads` endpoint.
+    pub fn to_search_ads_params(&self) -> Vec<(String, String)> {
+        let mut params = vec![
+            ("active_status".to_string(), self.active_status.as_param().to_string()),
+            ("ad_type".to_string(), self.ad_type.as_param().to_string()),
+            ("media_type".to_string(), self.media_type.as_param().to_string()),
+        ];
+
+        if self.countries.is_empty() {
+            params.push(("countries[0]".to_string(), "ALL".to_string()));
+        } else {
+            for (i, country) in self.countries.iter().enumerate() {
+                params.push((format!("countries[{}]", i), country.clone()));
+            }
+        }
+
+        for (i, platform) in self.publisher_platforms.iter().enumerate() {
+            params.push((format!("publisher_platforms[{}]", i), platform.as_param().to_string()));
+        }
+
+        if let Some(min) = self.delivery_date_min {
+            params.push(("start_date[min]".to_string(), min.format("%Y-%m-%d").to_string()));
+        }
+        if let Some(max) = self.delivery_date_max {
+            params.push(("start_date[max]".to_string(), max.format("%Y-%m-%d").to_string()));
+        }
+
+        params.push(("sort_data[direction]".to_string(), self.sort_direction.as_param().to_string()));
+        params.push(("sort_data[mode]".to_string(), self.sort_mode.as_param().to_string()));
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn param<'a>(params: &'a [(String, String)], key: &str) -> Option<&'a str> {
+        params.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn test_default_query_params() {
+        let params = AdQuery::new().to_search_ads_params();
+
+        assert_eq!(param(&params, "countries[0]"), Some("ALL"));
+        assert_eq!(param(&params, "active_status"), Some("all"));
+        assert_eq!(param(&params, "ad_type"), Some("all"));
+        assert_eq!(param(&params, "sort_data[mode]"), Some("relevancy_monthly_grouped"));
+        assert_eq!(param(&params, "sort_data[direction]"), Some("desc"));
+        assert_eq!(param(&params, "start_date[min]"), None);
+    }
+
+    #[test]
+    fn test_builder_query_params() {
+        let params = AdQuery::new()
+            .countries(["us", "GB"])
+            .active_status(ActiveStatus::Inactive)
+            .ad_type(AdType::Housing)
+            .media_type(MediaType::Video)
+            .publisher_platforms([PublisherPlatform::Instagram, PublisherPlatform::Facebook])
+            .delivery_date_range(NaiveDate::from_ymd_opt(2024, 1, 1), NaiveDate::from_ymd_opt(2024, 6, 30))
+            .unwrap()
+            .sort(SortMode::TotalImpressions, SortDirection::Asc)
+            .to_search_ads_params();
+
+        assert_eq!(param(&params, "countries[0]"), Some("US"));
+        assert_eq!(param(&params, "countries[1]"), Some("GB"));
+        assert_eq!(param(&params, "active_status"), Some("inactive"));
+        assert_eq!(param(&params, "ad_type"), Some("housing"));
+        assert_eq!(param(&params, "media_type"), Some("video"));
+        assert_eq!(param(&params, "publisher_platforms[0]"), Some("instagram"));
+        assert_eq!(param(&params, "publisher_platforms[1]"), Some("facebook"));
+        assert_eq!(param(&params, "start_date[min]"), Some("2024-01-01"));
+        assert_eq!(param(&params, "start_date[max]"), Some("2024-06-30"));
+        assert_eq!(param(&params, "sort_data[mode]"), Some("total_impressions"));
+        assert_eq!(param(&params, "sort_data[direction]"), Some("asc"));
+    }
+
+    #[test]
+    fn test_delivery_date_range_order() {
+        let date = |day| NaiveDate::from_ymd_opt(2024, 3, day);
+
+        assert!(AdQuery::new().delivery_date_range(date(2), date(1)).is_err());
+        assert!(AdQuery::new().delivery_date_range(date(1), date(1)).is_ok());
+        assert!(AdQuery::new().delivery_date_range(date(2), None).is_ok());
+    }
+
+    #[test]
+    fn test_enum_from_str() {
+        assert_eq!("political-and-issue-ads".parse::<AdType>().unwrap(), AdType::PoliticalAndIssueAds);
+        assert_eq!("Active".parse::<ActiveStatus>().unwrap(), ActiveStatus::Active);
+        assert_eq!("audience_network".parse::<PublisherPlatform>().unwrap(), PublisherPlatform::AudienceNetwork);
+        assert!("sometimes".parse::<ActiveStatus>().is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+
+/// A bucketed amount, as the Ad Library reports spend and impressions.
+///
+/// Bounds are inclusive; a missing upper bound means "or more" (`"1M+"`).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Range {
+    pub lower: Option<u64>,
+    pub upper: Option<u64>,
+    pub currency: Option<String>,
+}
+
+impl Range {
+    pub fn new(lower: Option<u64>, upper: Option<u64>) -> Self {
+        Range { lower, upper, currency: None }
+    }
+
+    pub fn with_currency(mut self, currency: Option<String>) -> Self {
+        self.currency = currency.filter(|c| !c.is_empty());
+        self
+    }
+
+    /// Parse the shapes the Ad Library uses: `"<100"`, `"100-199"`, `"1K-5K"`,
+    /// `"1M+"`, `">1000"`, plain numbers, and `{lower_bound, upper_bound}` objects.
+    pub fn parse(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(s) => Self::parse_str(s),
+            Value::Number(n) => n.as_u64().map(|n| Range::new(Some(n), Some(n))),
+            Value::Object(obj) => {
+                let bound = |key: &str| match obj.get(key)? {
+                    Value::String(s) => parse_amount(s),
+                    Value::Number(n) => n.as_u64(),
+                    _ => None,
+                };
+                let range = Range::new(bound("lower_bound"), bound("upper_bound"));
+                (range.lower.is_some() || range.upper.is_some()).then_some(range)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn parse_str(text: &str) -> Option<Self> {
+        let text: String = text
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != ',' && !is_currency_symbol(*c))
+            .collect();
+        if text.is_empty() {
+            return None;
+        }
+
+        if let Some(upper) = text.strip_prefix('<').or_else(|| text.strip_prefix('≤')) {
+            let upper = parse_amount(upper)?;
+            return Some(Range::new(Some(0), Some(upper.saturating_sub(1))));
+        }
+        if let Some(lower) = text.strip_prefix('>').or_else(|| text.strip_prefix('≥')) {
+            return Some(Range::new(Some(parse_amount(lower)?), None));
+        }
+        if let Some(lower) = text.strip_suffix('+') {
+            return Some(Range::new(Some(parse_amount(lower)?), None));
+        }
+        if let Some((lower, upper)) = text.split_once(['-', '–']) {
+            return Some(Range::new(Some(parse_amount(lower)?), Some(parse_amount(upper)?)));
+        }
+
+        let amount = parse_amount(&text)?;
+        Some(Range::new(Some(amount), Some(amount)))
+    }
+
+    /// A point estimate: the middle of the range, or the known bound for open ranges.
+    pub fn midpoint(&self) -> Option<f64> {
+        match (self.lower, self.upper) {
+            (Some(lower), Some(upper)) => Some((lower as f64 + upper as f64) / 2.0),
+            (Some(lower), None) => Some(lower as f64),
+            (None, Some(upper)) => Some(upper as f64 / 2.0),
+            (None, None) => None,
+        }
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.lower, self.upper) {
+            (Some(lower), Some(upper)) if lower == upper => write!(f, "{}", lower)?,
+            (Some(lower), Some(upper)) => write!(f, "{}-{}", lower, upper)?,
+            (Some(lower), None) => write!(f, "{}+", lower)?,
+            (None, Some(upper)) => write!(f, "<={}", upper)?,
+            (None, None) => {}
+        }
+        if let Some(currency) = &self.currency {
+            write!(f, " {}", currency)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_currency_symbol(c: char) -> bool {
+    matches!(c, '$' | '€' | '£' | '¥' | '₹' | '₩')
+}
+
+/// `"5"`, `"1.5K"`, `"2M"`, `"1B"` -> whole number.
+fn parse_amount(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let (number, multiplier) = match text.chars().last()? {
+        'k' | 'K' => (&text[..text.len() - 1], 1_000.0),
+        'm' | 'M' => (&text[..text.len() - 1], 1_000_000.0),
+        'b' | 'B' => (&text[..text.len() - 1], 1_000_000_000.0),
+        _ => (text, 1.0),
+    };
+    let value: f64 = number.parse().ok()?;
+    (value >= 0.0).then(|| (value * multiplier).round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_range_strings() {
+        assert_eq!(Range::parse_str("<100"), Some(Range::new(Some(0), Some(99))));
+        assert_eq!(Range::parse_str("100-199"), Some(Range::new(Some(100), Some(199))));
+        assert_eq!(Range::parse_str("1K-5K"), Some(Range::new(Some(1_000), Some(5_000))));
+        assert_eq!(Range::parse_str("$1,000 - $1,499"), Some(Range::new(Some(1_000), Some(1_499))));
+        assert_eq!(Range::parse_str("1.5M+"), Some(Range::new(Some(1_500_000), None)));
+        assert_eq!(Range::parse_str(">1000"), Some(Range::new(Some(1_000), None)));
+        assert_eq!(Range::parse_str("42"), Some(Range::new(Some(42), Some(42))));
+        assert_eq!(Range::parse_str(""), None);
+        assert_eq!(Range::parse_str("unknown"), None);
+    }
+
+    #[test]
+    fn test_parse_range_values() {
+        let range = Range::parse(&json!({"lower_bound": "100", "upper_bound": "199"})).unwrap();
+        assert_eq!(range, Range::new(Some(100), Some(199)));
+
+        let range = Range::parse(&json!({"lower_bound": 1000000})).unwrap();
+        assert_eq!(range, Range::new(Some(1_000_000), None));
+
+        assert_eq!(Range::parse(&json!(250)), Some(Range::new(Some(250), Some(250))));
+        assert_eq!(Range::parse(&json!({})), None);
+        assert_eq!(Range::parse(&Value::Null), None);
+    }
+
+    #[test]
+    fn test_midpoint_and_display() {
+        let range = Range::new(Some(100), Some(199)).with_currency(Some("USD".to_string()));
+        assert_eq!(range.midpoint(), Some(149.5));
+        assert_eq!(range.to_string(), "100-199 USD");
+
+        let range = Range::new(Some(1_000_000), None);
+        assert_eq!(range.midpoint(), Some(1_000_000.0));
+        assert_eq!(range.to_string(), "1000000+");
+    }
+}
@@ -0,0 +1,86 @@
+use anyhow::{Result, anyhow};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// The longest gap allowed between requests.
+const MAX_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Spaces requests out so no more than a set number start each second.
+///
+/// Share one limiter (behind an `Arc`) between every client that should count
+/// against the same budget, e.g. all the workers of a concurrent multi run.
+///
+/// ```
+/// # async fn run() -> anyhow::Result<()> {
+/// use facebook_totem::{FacebookTotem, RateLimiter};
+/// use std::sync::Arc;
+///
+/// let totem = FacebookTotem::builder()
+///     .rate_limiter(Arc::new(RateLimiter::per_second(2.0)?))
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next_slot: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn per_second(requests: f64) -> Result<Self> {
+        if !requests.is_finite() || requests <= 0.0 {
+            return Err(anyhow!("requests per second must be a positive number, got {}", requests));
+        }
+        // Much longer intervals would overflow when added to an `Instant`.
+        let interval = Duration::try_from_secs_f64(1.0 / requests)
+            .ok()
+            .filter(|interval| *interval <= MAX_INTERVAL)
+            .ok_or_else(|| anyhow!("requests per second must allow at least one request a day, got {}", requests))?;
+        Ok(RateLimiter { interval, next_slot: Mutex::new(None) })
+    }
+
+    /// Wait until the next request may be sent. Callers are served in the
+    /// order they ask, each one `interval` after the last.
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut next_slot = self.next_slot.lock().await;
+            let now = Instant::now();
+            let slot = next_slot.map_or(now, |slot| slot.max(now));
+            *next_slot = Some(slot + self.interval);
+            slot - now
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_per_second_rejects_non_positive_rates() {
+        assert!(RateLimiter::per_second(0.0).is_err());
+        assert!(RateLimiter::per_second(-1.0).is_err());
+        assert!(RateLimiter::per_second(f64::NAN).is_err());
+        assert!(RateLimiter::per_second(1e-300).is_err());
+        assert!(RateLimiter::per_second(f64::MIN_POSITIVE).is_err());
+        assert!(RateLimiter::per_second(1.0 / 86_400.0).is_ok());
+        assert!(RateLimiter::per_second(0.5).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_acquire_spaces_requests() {
+        let limiter = RateLimiter::per_second(20.0).unwrap();
+        let start = Instant::now();
+
+        for _ in 0..4 {
+            limiter.acquire().await;
+        }
+
+        // The first request goes straight away, the other three 50ms apart.
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+}
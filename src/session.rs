@@ -0,0 +1,130 @@
+use std::time::{Duration, Instant};
+
+use crate::error::error_payload;
+
+/// How long bootstrapped session tokens are reused before the landing page is fetched again.
+pub const DEFAULT_SESSION_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// Anti-CSRF and build tokens the Ad Library's async endpoints expect in
+/// every form body, scraped from the landing page.
+///
+/// Any token that could not be found is left as `None` and sent as an empty
+/// placeholder, as before bootstrapping existed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionTokens {
+    pub lsd: Option<String>,
+    pub jazoest: Option<String>,
+    pub spin_r: Option<String>,
+    pub spin_t: Option<String>,
+    pub rev: Option<String>,
+    pub hsi: Option<String>,
+    fetched_at: Instant,
+}
+
+impl SessionTokens {
+    pub fn is_expired(&self, ttl: Duration) -> bool {
+        self.fetched_at.elapsed() >= ttl
+    }
+}
+
+/// Pull the session tokens out of an Ad Library landing page.
+pub fn extract_session_tokens(html: &str) -> SessionTokens {
+    let lsd = quoted_after(html, "\"LSD\",[],{\"token\":\"")
+        .or_else(|| quoted_after(html, "name=\"lsd\" value=\""));
+    let jazoest = quoted_after(html, "name=\"jazoest\" value=\"")
+        .or_else(|| number_after(html, "jazoest="))
+        .or_else(|| lsd.as_deref().map(compute_jazoest));
+    let spin_r = number_after(html, "\"__spin_r\":");
+    let spin_t = number_after(html, "\"__spin_t\":");
+    let rev = number_after(html, "\"server_revision\":")
+        .or_else(|| number_after(html, "{\"rev\":"))
+        .or_else(|| spin_r.clone());
+    let hsi = quoted_after(html, "\"hsi\":\"");
+
+    SessionTokens {
+        lsd,
+        jazoest,
+        spin_r,
+        spin_t,
+        rev,
+        hsi,
+        fetched_at: Instant::now(),
+    }
+}
+
+/// Whether a `for (;;);`-prefixed response is Facebook's error payload, which
+/// is what stale session tokens get back instead of results.
+pub(crate) fn is_error_payload(text: &str) -> bool {
+    error_payload(text).is_some()
+}
+
+/// Facebook derives `jazoest` from the CSRF token: "2" followed by the sum of its character codes.
+fn compute_jazoest(token: &str) -> String {
+    let sum: u32 = token.chars().map(|c| c as u32).sum();
+    format!("2{}", sum)
+}
+
+fn quoted_after(html: &str, marker: &str) -> Option<String> {
+    let start = html.find(marker)? + marker.len();
+    let end = html[start..].find('"')?;
+    let value = &html[start..start + end];
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+fn number_after(html: &str, marker: &str) -> Option<String> {
+    let start = html.find(marker)? + marker.len();
+    let value: String = html[start..].chars().take_while(|c| c.is_ascii_digit()).collect();
+    (!value.is_empty()).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LANDING_PAGE: &str = r#"<html><script>
+        ["LSD",[],{"token":"AVqAbCdEf"},323]
+        {"server_revision":1012345678,"hsi":"7301234567890123456"}
+        {"__spin_r":1012345678,"__spin_b":"trunk","__spin_t":1700000000}
+        <input type="hidden" name="jazoest" value="2981" />
+    </script></html>"#;
+
+    #[test]
+    fn test_extract_session_tokens() {
+        let tokens = extract_session_tokens(LANDING_PAGE);
+
+        assert_eq!(tokens.lsd.as_deref(), Some("AVqAbCdEf"));
+        assert_eq!(tokens.jazoest.as_deref(), Some("2981"));
+        assert_eq!(tokens.spin_r.as_deref(), Some("1012345678"));
+        assert_eq!(tokens.spin_t.as_deref(), Some("1700000000"));
+        assert_eq!(tokens.rev.as_deref(), Some("1012345678"));
+        assert_eq!(tokens.hsi.as_deref(), Some("7301234567890123456"));
+    }
+
+    #[test]
+    fn test_extract_session_tokens_fallbacks() {
+        let tokens = extract_session_tokens(r#"<input name="lsd" value="AB" />{"__spin_r":42}"#);
+
+        assert_eq!(tokens.lsd.as_deref(), Some("AB"));
+        assert_eq!(tokens.jazoest.as_deref(), Some("2131"));
+        assert_eq!(tokens.rev.as_deref(), Some("42"));
+        assert_eq!(tokens.hsi, None);
+
+        let tokens = extract_session_tokens("<html>login required</html>");
+        assert_eq!(tokens.lsd, None);
+        assert_eq!(tokens.jazoest, None);
+    }
+
+    #[test]
+    fn test_session_expiry() {
+        let tokens = extract_session_tokens(LANDING_PAGE);
+        assert!(!tokens.is_expired(DEFAULT_SESSION_TTL));
+        assert!(tokens.is_expired(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_is_error_payload() {
+        assert!(is_error_payload(r#"for (;;);{"__ar":1,"error":1357004,"errorSummary":"Sorry, something went wrong"}"#));
+        assert!(!is_error_payload(r#"for (;;);{"payload":{"results":[]}}"#));
+        assert!(!is_error_payload("<html></html>"));
+    }
+}
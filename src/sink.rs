@@ -0,0 +1,474 @@
+use anyhow::Result;
+use csv::Writer;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use crate::ad::{RANGE_COLUMNS, has_range_fields, range_column_values};
+use crate::fields::Projection;
+use crate::flatten::NON_OBJECT_COLUMN;
+use crate::{FacebookAd, FacebookPage, OutputOptions, flatten_rows};
+
+/// Somewhere records are written to.
+///
+/// A sink is opened once, given every record, then finished; the `write_*_to_sink`
+/// functions drive that sequence. Records are the raw JSON objects of ads or
+/// pages. Implement this to send results somewhere other than the built-in
+/// [`CsvSink`], [`JsonSink`] and [`JsonlSink`].
+pub trait OutputSink {
+    /// Called once before the first record.
+    fn open(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &Value) -> Result<()>;
+
+    /// Called once after the last record. Anything still buffered must be written out.
+    fn finish(&mut self) -> Result<()>;
+}
+
+impl<S: OutputSink + ?Sized> OutputSink for Box<S> {
+    fn open(&mut self) -> Result<()> {
+        (**self).open()
+    }
+
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        (**self).write_record(record)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        (**self).finish()
+    }
+}
+
+/// Open `sink`, write every row to it and finish it.
+pub fn write_json_to_sink<'a>(data: impl IntoIterator<Item = &'a Value>, sink: &mut dyn OutputSink) -> Result<()> {
+    sink.open()?;
+    for record in data {
+        sink.write_record(record)?;
+    }
+    sink.finish()
+}
+
+/// Write ads' raw response objects to `sink`.
+pub fn write_ads_to_sink(ads: &[FacebookAd], sink: &mut dyn OutputSink) -> Result<()> {
+    write_json_to_sink(ads.iter().map(|ad| &ad.raw), sink)
+}
+
+pub fn write_facebook_pages_to_sink(pages: &[FacebookPage], sink: &mut dyn OutputSink) -> Result<()> {
+    let json_values: Vec<Value> = pages.iter()
+        .map(|page| serde_json::to_value(page).unwrap_or(Value::Null))
+        .collect();
+    write_json_to_sink(&json_values, sink)
+}
+
+/// A file that is only created once something is written to it, so a run
+/// that produces nothing (or fails before writing) leaves no file behind.
+pub struct LazyFile {
+    path: PathBuf,
+    file: Option<BufWriter<File>>,
+}
+
+impl LazyFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        LazyFile { path: path.into(), file: None }
+    }
+}
+
+impl Write for LazyFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert(BufWriter::new(File::create(&self.path)?)),
+        };
+        file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Flattening and field projection, applied to each record on its way in.
+struct Shaping {
+    options: OutputOptions,
+    projection: Option<Projection>,
+    first_checked: bool,
+}
+
+impl Shaping {
+    fn new(options: OutputOptions) -> Self {
+        let projection = options.fields.clone().map(Projection::new);
+        Shaping { options, projection, first_checked: false }
+    }
+
+    /// Check the fields against the first record's rows before any of them
+    /// is written (see [`Projection::check_first`]).
+    fn check_first(&mut self, rows: &[Value]) -> Result<()> {
+        if self.first_checked || rows.is_empty() {
+            return Ok(());
+        }
+        if let Some(projection) = &self.projection {
+            projection.check_first(rows)?;
+        }
+        self.first_checked = true;
+        Ok(())
+    }
+
+    fn flatten(&self, record: &Value) -> Result<Vec<Value>> {
+        match &self.options.flatten {
+            Some(flatten) => flatten_rows(std::slice::from_ref(record), flatten),
+            None => Ok(vec![record.clone()]),
+        }
+    }
+
+    /// Rows as they go into JSON output: flattened and projected if requested,
+    /// otherwise untouched so nested structure and types survive.
+    fn json_rows(&mut self, record: &Value) -> Result<Vec<Value>> {
+        let rows = self.flatten(record)?;
+        self.check_first(&rows)?;
+        Ok(match &mut self.projection {
+            Some(projection) => rows.iter().map(|row| projection.project_object(row)).collect(),
+            None => rows,
+        })
+    }
+
+    fn check(&self) -> Result<()> {
+        self.projection.as_ref().map_or(Ok(()), Projection::check)
+    }
+}
+
+/// Writes CSV. With [`OutputOptions::fields`] the header is known up front,
+/// so rows are written and flushed as they come in. Otherwise the header is
+/// the union of every row's keys, in the order they are first seen, and rows
+/// are buffered until [`OutputSink::finish`].
+pub struct CsvSink<W: Write> {
+    writer: Option<W>,
+    csv: Option<Writer<W>>,
+    shaping: Shaping,
+    rows: Vec<Value>,
+}
+
+impl CsvSink<LazyFile> {
+    pub fn create(filename: &str, options: OutputOptions) -> Self {
+        CsvSink::new(LazyFile::new(filename), options)
+    }
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(writer: W, options: OutputOptions) -> Self {
+        CsvSink { writer: Some(writer), csv: None, shaping: Shaping::new(options), rows: Vec::new() }
+    }
+}
+
+impl<W: Write> OutputSink for CsvSink<W> {
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        let rows = self.shaping.flatten(record)?;
+        self.shaping.check_first(&rows)?;
+        let Some(projection) = &mut self.shaping.projection else {
+            self.rows.extend(rows);
+            return Ok(());
+        };
+        if rows.is_empty() {
+            return Ok(());
+        }
+        // The header goes out with the first row, so no rows leave no output.
+        let csv = match (&mut self.csv, self.writer.take()) {
+            (Some(csv), _) => csv,
+            (None, Some(writer)) => {
+                let mut csv = Writer::from_writer(writer);
+                csv.write_record(projection.names())?;
+                self.csv.insert(csv)
+            }
+            (None, None) => return Ok(()),
+        };
+        for row in &rows {
+            csv.write_record(projection.project(row).iter().map(csv_cell))?;
+        }
+        csv.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(projection) = &self.shaping.projection {
+            if let Some(csv) = &mut self.csv {
+                csv.flush()?;
+            }
+            return projection.check();
+        }
+        let Some(writer) = self.writer.take() else {
+            return Ok(());
+        };
+        write_rows_to_csv(writer, &std::mem::take(&mut self.rows))
+    }
+}
+
+fn write_rows_to_csv<W: Write>(writer: W, data: &[Value]) -> Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+
+    let mut wtr = Writer::from_writer(writer);
+
+    let headers = union_headers(data);
+    // Ads get their spend and impressions buckets split into numeric columns.
+    let with_ranges = data.iter().any(has_range_fields)
+        && !headers.iter().any(|header| RANGE_COLUMNS.contains(&header.as_str()));
+
+    if with_ranges {
+        wtr.write_record(headers.iter().map(String::as_str).chain(RANGE_COLUMNS))?;
+    } else {
+        wtr.write_record(&headers)?;
+    }
+
+    for item in data {
+        let mut record: Vec<String> = headers.iter()
+            .map(|header| match item {
+                Value::Object(obj) => obj.get(header).map(csv_cell).unwrap_or_default(),
+                other if header == NON_OBJECT_COLUMN => csv_cell(other),
+                _ => String::new(),
+            })
+            .collect();
+        if with_ranges {
+            record.extend(range_column_values(item));
+        }
+        wtr.write_record(&record)?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+fn union_headers(data: &[Value]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut headers = Vec::new();
+    for item in data {
+        let keys: Vec<&str> = match item {
+            Value::Object(obj) => obj.keys().map(String::as_str).collect(),
+            _ => vec![NON_OBJECT_COLUMN],
+        };
+        for key in keys {
+            if seen.insert(key) {
+                headers.push(key.to_string());
+            }
+        }
+    }
+    headers
+}
+
+fn csv_cell(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Null => String::new(),
+        _ => serde_json::to_string(value).unwrap_or_default(),
+    }
+}
+
+/// Writes one JSON array, a record at a time. Like the other sinks, it
+/// writes nothing at all if there are no records.
+pub struct JsonSink<W: Write> {
+    writer: W,
+    shaping: Shaping,
+    written: usize,
+}
+
+impl JsonSink<LazyFile> {
+    pub fn create(filename: &str, options: OutputOptions) -> Self {
+        JsonSink::new(LazyFile::new(filename), options)
+    }
+}
+
+impl<W: Write> JsonSink<W> {
+    pub fn new(writer: W, options: OutputOptions) -> Self {
+        JsonSink { writer, shaping: Shaping::new(options), written: 0 }
+    }
+}
+
+impl<W: Write> OutputSink for JsonSink<W> {
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        for row in self.shaping.json_rows(record)? {
+            // The array is opened with its first row, so no rows leave no output.
+            let separator = if self.written == 0 { "[\n" } else { ",\n" };
+            write!(self.writer, "{}", separator)?;
+            serde_json::to_writer_pretty(&mut self.writer, &row)?;
+            self.written += 1;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.written > 0 {
+            write!(self.writer, "\n]\n")?;
+        }
+        self.writer.flush()?;
+        self.shaping.check()
+    }
+}
+
+/// Writes JSON Lines, flushing after every record so output can be piped
+/// into another program while results are still coming in.
+pub struct JsonlSink<W: Write> {
+    writer: W,
+    shaping: Shaping,
+}
+
+impl JsonlSink<LazyFile> {
+    pub fn create(filename: &str, options: OutputOptions) -> Self {
+        JsonlSink::new(LazyFile::new(filename), options)
+    }
+}
+
+impl<W: Write> JsonlSink<W> {
+    pub fn new(writer: W, options: OutputOptions) -> Self {
+        JsonlSink { writer, shaping: Shaping::new(options) }
+    }
+}
+
+impl<W: Write> OutputSink for JsonlSink<W> {
+    fn write_record(&mut self, record: &Value) -> Result<()> {
+        for row in self.shaping.json_rows(record)? {
+            serde_json::to_writer(&mut self.writer, &row)?;
+            writeln!(self.writer)?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        self.shaping.check()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_fields;
+    use serde_json::json;
+
+    /// A sink that keeps records in memory, like a user-supplied one would.
+    #[derive(Default)]
+    struct MemorySink {
+        opened: bool,
+        records: Vec<Value>,
+        finished: bool,
+    }
+
+    impl OutputSink for MemorySink {
+        fn open(&mut self) -> Result<()> {
+            self.opened = true;
+            Ok(())
+        }
+
+        fn write_record(&mut self, record: &Value) -> Result<()> {
+            self.records.push(record.clone());
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<()> {
+            self.finished = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_ads_to_custom_sink() {
+        let ads: Vec<FacebookAd> = vec![json!({"adArchiveID": "1"}).into(), json!({"adArchiveID": "2"}).into()];
+        let mut sink = MemorySink::default();
+
+        write_ads_to_sink(&ads, &mut sink).unwrap();
+
+        assert!(sink.opened && sink.finished);
+        assert_eq!(sink.records, vec![ads[0].raw.clone(), ads[1].raw.clone()]);
+    }
+
+    #[test]
+    fn test_json_sink_to_buffer() {
+        let mut buffer = Vec::new();
+        let mut sink = JsonSink::new(&mut buffer, OutputOptions::default());
+        write_json_to_sink(&[json!({"a": 1}), json!([2])], &mut sink).unwrap();
+
+        let written: Value = serde_json::from_slice(&buffer).unwrap();
+        assert_eq!(written, json!([{"a": 1}, [2]]));
+
+        let mut buffer = Vec::new();
+        write_json_to_sink(&[], &mut JsonSink::new(&mut buffer, OutputOptions::default())).unwrap();
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_jsonl_sink_reports_unmatched_fields_on_finish() {
+        let options = OutputOptions { fields: Some(parse_fields("a,b.c").unwrap()), ..Default::default() };
+        let mut buffer = Vec::new();
+        let mut sink = JsonlSink::new(&mut buffer, options);
+        sink.open().unwrap();
+        sink.write_record(&json!({"a": 1, "b": {}})).unwrap();
+
+        assert!(sink.finish().unwrap_err().to_string().contains("b.c"));
+        drop(sink);
+        assert_eq!(buffer, b"{\"a\":1,\"b.c\":null}\n");
+    }
+
+    #[test]
+    fn test_sinks_reject_unknown_fields_before_writing() {
+        let options = || OutputOptions { fields: Some(parse_fields("a,typo").unwrap()), ..Default::default() };
+        let record = json!({"a": 1});
+        let mut buffers = [Vec::new(), Vec::new(), Vec::new()];
+        let [csv, json, jsonl] = &mut buffers;
+        let mut sinks: [Box<dyn OutputSink + '_>; 3] =
+            [Box::new(CsvSink::new(csv, options())), Box::new(JsonSink::new(json, options())), Box::new(JsonlSink::new(jsonl, options()))];
+
+        for sink in &mut sinks {
+            sink.open().unwrap();
+            assert!(sink.write_record(&record).unwrap_err().to_string().contains("typo"));
+        }
+        drop(sinks);
+        assert!(buffers.iter().all(Vec::is_empty));
+    }
+
+    #[test]
+    fn test_csv_sink_with_fields_writes_rows_as_they_come() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("ads.csv");
+        let options = OutputOptions { fields: Some(parse_fields("a,b").unwrap()), ..Default::default() };
+        let mut sink = CsvSink::create(path.to_str().unwrap(), options);
+
+        sink.open().unwrap();
+        sink.write_record(&json!({"a": 1, "b": "x"})).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a,b\n1,x\n");
+
+        sink.write_record(&json!({"a": 2})).unwrap();
+        sink.finish().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a,b\n1,x\n2,\n");
+    }
+
+    #[test]
+    fn test_csv_sink_leaves_no_file_without_rows() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("empty.csv");
+
+        write_json_to_sink(&[], &mut CsvSink::create(path.to_str().unwrap(), OutputOptions::default())).unwrap();
+
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_json_sinks_leave_no_file_without_rows() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let json = temp_dir.path().join("empty.json");
+        let jsonl = temp_dir.path().join("empty.jsonl");
+
+        write_json_to_sink(&[], &mut JsonSink::create(json.to_str().unwrap(), OutputOptions::default())).unwrap();
+        write_json_to_sink(&[], &mut JsonlSink::create(jsonl.to_str().unwrap(), OutputOptions::default())).unwrap();
+
+        assert!(!json.exists());
+        assert!(!jsonl.exists());
+    }
+}
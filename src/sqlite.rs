@@ -0,0 +1,388 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, Transaction, params};
+use serde_json::Value;
+use std::path::Path;
+
+use crate::{FacebookAd, FacebookPage};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS pages (
+    page_id TEXT PRIMARY KEY,
+    page_name TEXT,
+    profile_picture_uri TEXT,
+    page_uri TEXT,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS ads (
+    ad_archive_id TEXT PRIMARY KEY,
+    page_id TEXT REFERENCES pages (page_id),
+    start_date TEXT,
+    end_date TEXT,
+    is_active INTEGER,
+    spend_lower INTEGER,
+    spend_upper INTEGER,
+    impressions_lower INTEGER,
+    impressions_upper INTEGER,
+    currency TEXT,
+    publisher_platforms TEXT,
+    body TEXT,
+    title TEXT,
+    link_url TEXT,
+    cta_text TEXT,
+    snapshot_url TEXT,
+    raw TEXT NOT NULL,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS ads_page_id ON ads (page_id);
+
+CREATE TABLE IF NOT EXISTS ad_cards (
+    ad_archive_id TEXT NOT NULL REFERENCES ads (ad_archive_id) ON DELETE CASCADE,
+    card_index INTEGER NOT NULL,
+    title TEXT,
+    body TEXT,
+    link_url TEXT,
+    cta_text TEXT,
+    image_url TEXT,
+    video_url TEXT,
+    raw TEXT NOT NULL,
+    PRIMARY KEY (ad_archive_id, card_index)
+);
+
+CREATE TABLE IF NOT EXISTS targets (
+    source_target TEXT PRIMARY KEY,
+    page_id TEXT REFERENCES pages (page_id),
+    username TEXT,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL
+);
+";
+
+/// A SQLite database of pages, ads and ad cards, plus the multi mode
+/// targets each page was found through.
+///
+/// Rows are keyed by page ID and ad archive ID and upserted, so the same
+/// database can collect results across runs: a re-fetched ad is updated in
+/// place (keeping its `first_seen`) and its cards are replaced. Ads without
+/// an archive ID can't be keyed and are skipped.
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    /// Open or create a database at `path`, creating the tables if needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self> {
+        // SQLite leaves foreign keys, and so the card cascade, off per connection.
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Insert or update pages. Returns the number of pages written.
+    pub fn upsert_pages(&mut self, pages: &[FacebookPage]) -> Result<usize> {
+        let now = Utc::now().to_rfc3339();
+        let tx = self.conn.transaction()?;
+        let mut written = 0;
+        for page in pages {
+            let Some(page_id) = &page.page_id else { continue };
+            upsert_page(&tx, page_id, page.page_name.as_deref(), page.page_profile_picture_uri.as_deref(), page.page_uri.as_deref(), &now)?;
+            written += 1;
+        }
+        tx.commit()?;
+        Ok(written)
+    }
+
+    /// Insert or update ads with their cards, adding a row for each ad's page
+    /// if it isn't there yet. Returns the number of ads written.
+    pub fn upsert_ads(&mut self, ads: &[FacebookAd]) -> Result<usize> {
+        let now = Utc::now().to_rfc3339();
+        let tx = self.conn.transaction()?;
+        let mut written = 0;
+        for ad in ads {
+            let Some(ad_archive_id) = &ad.ad_archive_id else { continue };
+            if let Some(page_id) = &ad.page_id {
+                upsert_page(&tx, page_id, ad.page_name.as_deref(), None, None, &now)?;
+            }
+            upsert_ad(&tx, ad_archive_id, ad, &now)?;
+            replace_cards(&tx, ad_archive_id, &ad.raw)?;
+            written += 1;
+        }
+        tx.commit()?;
+        Ok(written)
+    }
+
+    /// Record that `source_target` (a URL, page ID or name from a multi mode
+    /// input) resolved to `page_id`.
+    pub fn upsert_target(&mut self, source_target: &str, page_id: &str, username: Option<&str>) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        let tx = self.conn.transaction()?;
+        upsert_page(&tx, page_id, None, None, None, &now)?;
+        tx.execute(
+            "INSERT INTO targets (source_target, page_id, username, first_seen, last_seen)
+             VALUES (?1, ?2, ?3, ?4, ?4)
+             ON CONFLICT (source_target) DO UPDATE SET
+                 page_id = excluded.page_id,
+                 username = excluded.username,
+                 last_seen = excluded.last_seen",
+            params![source_target, page_id, username, now],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+/// Write pages to the SQLite database at `path`, creating it if needed.
+pub fn write_facebook_pages_to_sqlite(pages: &[FacebookPage], path: &str) -> Result<()> {
+    SqliteStore::open(path)?.upsert_pages(pages)?;
+    Ok(())
+}
+
+/// Write ads and their cards to the SQLite database at `path`, creating it if needed.
+pub fn write_ads_to_sqlite(ads: &[FacebookAd], path: &str) -> Result<()> {
+    SqliteStore::open(path)?.upsert_ads(ads)?;
+    Ok(())
+}
+
+fn upsert_page(tx: &Transaction, page_id: &str, name: Option<&str>, picture: Option<&str>, uri: Option<&str>, now: &str) -> Result<()> {
+    // Pages seen only through their ads have no picture or URI; don't let
+    // them blank out what a search run stored.
+    tx.execute(
+        "INSERT INTO pages (page_id, page_name, profile_picture_uri, page_uri, first_seen, last_seen)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+         ON CONFLICT (page_id) DO UPDATE SET
+             page_name = COALESCE(excluded.page_name, page_name),
+             profile_picture_uri = COALESCE(excluded.profile_picture_uri, profile_picture_uri),
+             page_uri = COALESCE(excluded.page_uri, page_uri),
+             last_seen = excluded.last_seen",
+        params![page_id, name, picture, uri, now],
+    )?;
+    Ok(())
+}
+
+fn upsert_ad(tx: &Transaction, ad_archive_id: &str, ad: &FacebookAd, now: &str) -> Result<()> {
+    let date = |date: &Option<DateTime<Utc>>| date.map(|date| date.to_rfc3339());
+    let bound = |bound: Option<u64>| bound.and_then(|bound| i64::try_from(bound).ok());
+    let platforms = (!ad.publisher_platforms.is_empty()).then(|| ad.publisher_platforms.join(","));
+
+    tx.execute(
+        "INSERT INTO ads (
+             ad_archive_id, page_id, start_date, end_date, is_active,
+             spend_lower, spend_upper, impressions_lower, impressions_upper, currency,
+             publisher_platforms, body, title, link_url, cta_text, snapshot_url, raw,
+             first_seen, last_seen
+         )
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?18)
+         ON CONFLICT (ad_archive_id) DO UPDATE SET
+             page_id = excluded.page_id,
+             start_date = excluded.start_date,
+             end_date = excluded.end_date,
+             is_active = excluded.is_active,
+             spend_lower = excluded.spend_lower,
+             spend_upper = excluded.spend_upper,
+             impressions_lower = excluded.impressions_lower,
+             impressions_upper = excluded.impressions_upper,
+             currency = excluded.currency,
+             publisher_platforms = excluded.publisher_platforms,
+             body = excluded.body,
+             title = excluded.title,
+             link_url = excluded.link_url,
+             cta_text = excluded.cta_text,
+             snapshot_url = excluded.snapshot_url,
+             raw = excluded.raw,
+             last_seen = excluded.last_seen",
+        params![
+            ad_archive_id,
+            ad.page_id,
+            date(&ad.start_date),
+            date(&ad.end_date),
+            ad.is_active,
+            bound(ad.spend.as_ref().and_then(|r| r.lower)),
+            bound(ad.spend.as_ref().and_then(|r| r.upper)),
+            bound(ad.impressions.as_ref().and_then(|r| r.lower)),
+            bound(ad.impressions.as_ref().and_then(|r| r.upper)),
+            ad.currency,
+            platforms,
+            ad.body,
+            ad.title,
+            ad.link_url,
+            ad.cta_text,
+            ad.snapshot_url,
+            ad.raw.to_string(),
+            now,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Carousel ads list their cards under `snapshot.cards`.
+fn replace_cards(tx: &Transaction, ad_archive_id: &str, raw: &Value) -> Result<()> {
+    tx.execute("DELETE FROM ad_cards WHERE ad_archive_id = ?1", params![ad_archive_id])?;
+
+    let cards = raw.pointer("/snapshot/cards").and_then(Value::as_array);
+    for (index, card) in cards.into_iter().flatten().enumerate() {
+        let text = |keys: &[&str]| keys.iter().find_map(|key| card_text(card.get(key)?));
+        tx.execute(
+            "INSERT INTO ad_cards (ad_archive_id, card_index, title, body, link_url, cta_text, image_url, video_url, raw)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                ad_archive_id,
+                index as i64,
+                text(&["title"]),
+                text(&["body"]),
+                text(&["link_url"]),
+                text(&["cta_text"]),
+                text(&["original_image_url", "resized_image_url"]),
+                text(&["video_hd_url", "video_sd_url"]),
+                card.to_string(),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// Card text is either a plain string or a `{text}` / `{markup: {__html}}` object.
+fn card_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Object(_) => value
+            .get("text")
+            .or_else(|| value.pointer("/markup/__html"))
+            .and_then(card_text),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn count(store: &SqliteStore, table: &str) -> i64 {
+        store
+            .connection()
+            .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0))
+            .unwrap()
+    }
+
+    fn carousel_ad(spend: &str, cards: Value) -> FacebookAd {
+        json!({
+            "adArchiveID": "1",
+            "pageID": "10",
+            "pageName": "Test Page",
+            "isActive": true,
+            "spend": spend,
+            "publisherPlatform": ["FACEBOOK", "INSTAGRAM"],
+            "snapshot": {"cards": cards}
+        })
+        .into()
+    }
+
+    #[test]
+    fn test_upsert_ads_with_cards() {
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        let ad = carousel_ad("<100", json!([
+            {"title": "A", "body": {"markup": {"__html": "First"}}, "original_image_url": "https://example.com/a.jpg"},
+            {"title": "B", "video_sd_url": "https://example.com/b.mp4"}
+        ]));
+
+        assert_eq!(store.upsert_ads(&[ad, json!({"pageID": "10"}).into()]).unwrap(), 1);
+
+        let (page_id, is_active, spend_upper, platforms): (String, bool, i64, String) = store
+            .connection()
+            .query_row("SELECT page_id, is_active, spend_upper, publisher_platforms FROM ads", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap();
+        assert_eq!((page_id.as_str(), is_active, spend_upper, platforms.as_str()), ("10", true, 99, "facebook,instagram"));
+
+        let cards: Vec<[Option<String>; 4]> = store
+            .connection()
+            .prepare("SELECT title, body, image_url, video_url FROM ad_cards ORDER BY card_index")
+            .unwrap()
+            .query_map([], |row| Ok([row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?]))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        let owned = |cells: [Option<&str>; 4]| cells.map(|cell| cell.map(str::to_string));
+        assert_eq!(cards[0], owned([Some("A"), Some("First"), Some("https://example.com/a.jpg"), None]));
+        assert_eq!(cards[1], owned([Some("B"), None, None, Some("https://example.com/b.mp4")]));
+        assert_eq!(count(&store, "pages"), 1);
+    }
+
+    #[test]
+    fn test_upsert_ads_updates_on_rerun() {
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        store.upsert_ads(&[carousel_ad("<100", json!([{"title": "A"}, {"title": "B"}]))]).unwrap();
+        store.upsert_ads(&[carousel_ad("100-199", json!([{"title": "C"}]))]).unwrap();
+
+        assert_eq!(count(&store, "ads"), 1);
+        assert_eq!(count(&store, "ad_cards"), 1);
+        let spend_lower: i64 = store.connection().query_row("SELECT spend_lower FROM ads", [], |row| row.get(0)).unwrap();
+        assert_eq!(spend_lower, 100);
+    }
+
+    #[test]
+    fn test_foreign_keys_enforced() {
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        store.upsert_ads(&[carousel_ad("<100", json!([{"title": "A"}, {"title": "B"}]))]).unwrap();
+
+        store.connection().execute("DELETE FROM ads", []).unwrap();
+        assert_eq!(count(&store, "ad_cards"), 0);
+
+        let orphan = store.connection().execute("INSERT INTO ads (ad_archive_id, page_id, raw, first_seen, last_seen) VALUES ('1', 'missing', '{}', '', '')", []);
+        assert!(orphan.is_err());
+    }
+
+    #[test]
+    fn test_upsert_pages_keeps_search_details() {
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        let page = FacebookPage {
+            page_id: Some("10".to_string()),
+            page_name: Some("Test Page".to_string()),
+            page_profile_picture_uri: Some("test.jpg".to_string()),
+            page_uri: Some("testpage".to_string()),
+        };
+
+        store.upsert_pages(&[page]).unwrap();
+        store.upsert_ads(&[carousel_ad("<100", json!([]))]).unwrap();
+
+        let (name, uri): (String, String) = store
+            .connection()
+            .query_row("SELECT page_name, page_uri FROM pages WHERE page_id = '10'", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((name.as_str(), uri.as_str()), ("Test Page", "testpage"));
+        assert_eq!(count(&store, "pages"), 1);
+    }
+
+    #[test]
+    fn test_upsert_target() {
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        store.upsert_ads(&[carousel_ad("<100", json!([]))]).unwrap();
+
+        store.upsert_target("https://facebook.com/testpage", "10", Some("testpage")).unwrap();
+        store.upsert_target("https://facebook.com/testpage", "10", Some("testpage")).unwrap();
+
+        let (target, ads): (String, i64) = store
+            .connection()
+            .query_row("SELECT source_target, COUNT(*) FROM targets JOIN ads USING (page_id)", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((target.as_str(), ads), ("https://facebook.com/testpage", 1));
+        assert_eq!(count(&store, "pages"), 1);
+    }
+}
@@ -0,0 +1,311 @@
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+
+use crate::{FacebookTotem, PageRef};
+
+/// Column names tried, in order, when no column is given.
+const TARGET_COLUMNS: &[&str] = &["url", "page_url", "link", "page_id", "id", "page_name", "name", "page", "username"];
+
+/// A page to fetch ads for in multi mode.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Target {
+    /// A Facebook page URL.
+    Url(String),
+    /// A numeric page ID, used as is.
+    PageId(String),
+    /// A page name, looked up with [`FacebookTotem::get_facebook_page_from_name`].
+    Name(String),
+}
+
+impl Target {
+    /// Classify one input value: all digits is a page ID, something with a
+    /// scheme or `facebook.com` in it is a URL, and anything else is a name.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if value.is_empty() {
+            return None;
+        }
+        if value.chars().all(|c| c.is_ascii_digit()) {
+            Some(Target::PageId(value.to_string()))
+        } else if value.contains("://") || value.contains("facebook.com") {
+            Some(Target::Url(value.trim_end_matches('/').to_string()))
+        } else {
+            Some(Target::Name(value.to_string()))
+        }
+    }
+
+    /// The vanity name or name slug in a URL (see [`PageRef`]), the name of a
+    /// name target, or "" if there is neither.
+    pub fn username(&self) -> String {
+        match self {
+            Target::Url(url) => PageRef::parse(url).and_then(|page| page.username).unwrap_or_default(),
+            Target::Name(name) => name.clone(),
+            Target::PageId(_) => String::new(),
+        }
+    }
+
+    /// Look up the target's page ID. Names resolve to the search result with
+    /// exactly that name, or the first result if none match exactly.
+    pub async fn page_id(&self, totem: &FacebookTotem) -> Result<String> {
+        match self {
+            Target::PageId(id) => Ok(id.clone()),
+            Target::Url(url) => totem.get_id_from_url(url).await,
+            Target::Name(name) => {
+                let pages = totem.get_facebook_page_from_name(name).await?;
+                let exact = pages.iter().find(|page| page.page_name.as_deref().is_some_and(|page_name| page_name.eq_ignore_ascii_case(name)));
+                exact
+                    .or(pages.first())
+                    .and_then(|page| page.page_id.clone())
+                    .ok_or_else(|| anyhow!("no page found named '{}'", name))
+            }
+        }
+    }
+
+    fn dedup_key(&self) -> DedupKey {
+        let page = match self {
+            Target::PageId(id) => return DedupKey::PageId(id.clone()),
+            Target::Url(url) => PageRef::parse(url),
+            Target::Name(_) => None,
+        };
+        match page {
+            Some(PageRef { page_id: Some(page_id), .. }) => DedupKey::PageId(page_id),
+            Some(PageRef { username: Some(username), .. }) => DedupKey::Username(username.to_lowercase()),
+            _ => DedupKey::Target(self.clone()),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Url(value) | Target::PageId(value) | Target::Name(value) => f.write_str(value),
+        }
+    }
+}
+
+/// How a list of targets is laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    /// A CSV file with a header row.
+    Csv,
+    /// One JSON object (or string) per line.
+    Jsonl,
+    /// One target per line. Blank lines and `#` comments are skipped.
+    Lines,
+}
+
+impl InputFormat {
+    /// Pick a format from the file extension, or from the first line when
+    /// the extension doesn't say (e.g. stdin). Without an extension, text is
+    /// only CSV if `column` is given or the first line names one of the
+    /// usual target columns, so a list starting with `Smith, John` stays a
+    /// list.
+    pub fn detect(path: Option<&Path>, text: &str, column: Option<&str>) -> Self {
+        match path.and_then(|path| path.extension()).and_then(|extension| extension.to_str()) {
+            Some("csv") => return InputFormat::Csv,
+            Some("jsonl" | "ndjson") => return InputFormat::Jsonl,
+            Some("txt") => return InputFormat::Lines,
+            _ => {}
+        }
+
+        let first_line = text.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or("");
+        if first_line.starts_with('{') || first_line.starts_with('"') {
+            InputFormat::Jsonl
+        } else if column.is_some()
+            || first_line
+                .split(',')
+                .any(|field| TARGET_COLUMNS.contains(&field.trim().trim_matches('"').to_lowercase().as_str()))
+        {
+            InputFormat::Csv
+        } else {
+            InputFormat::Lines
+        }
+    }
+}
+
+/// Read targets from `path`, or stdin for `-`. See [`parse_targets`].
+pub fn read_targets(path: &str, column: Option<&str>) -> Result<Vec<Target>> {
+    let (text, path) = if path == "-" {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text)?;
+        (text, None)
+    } else {
+        (std::fs::read_to_string(path).map_err(|e| anyhow!("couldn't read targets from {}: {}", path, e))?, Some(Path::new(path)))
+    };
+    parse_targets(&text, InputFormat::detect(path, &text, column), column)
+}
+
+/// Parse a list of targets, dropping repeats. `column` picks the CSV column
+/// or JSON key to read; without it the first of `url`, `page_url`, `link`,
+/// `page_id`, `id`, `page_name`, `name`, `page` and `username` that exists is
+/// used, or the only column if there is just one.
+pub fn parse_targets(text: &str, format: InputFormat, column: Option<&str>) -> Result<Vec<Target>> {
+    let values = match format {
+        InputFormat::Lines => text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect(),
+        InputFormat::Csv => csv_values(text, column)?,
+        InputFormat::Jsonl => jsonl_values(text, column)?,
+    };
+
+    let mut seen = HashSet::new();
+    Ok(values
+        .iter()
+        .filter_map(|value| Target::parse(value))
+        .filter(|target| seen.insert(target.dedup_key()))
+        .collect())
+}
+
+/// What makes two targets the same page: the page ID or lowercased vanity
+/// name when a URL or ID says, otherwise the target itself.
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum DedupKey {
+    PageId(String),
+    Username(String),
+    Target(Target),
+}
+
+fn csv_values(text: &str, column: Option<&str>) -> Result<Vec<String>> {
+    let mut reader = csv::Reader::from_reader(text.as_bytes());
+    let headers: Vec<String> = reader.headers()?.iter().map(str::to_string).collect();
+    let index = pick_column(&headers, column)?;
+
+    let mut values = Vec::new();
+    for record in reader.records() {
+        if let Some(value) = record?.get(index) {
+            values.push(value.to_string());
+        }
+    }
+    Ok(values)
+}
+
+fn jsonl_values(text: &str, column: Option<&str>) -> Result<Vec<String>> {
+    let mut values = Vec::new();
+    let mut key: Option<String> = column.map(str::to_string);
+    for (number, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let value: Value = serde_json::from_str(line).map_err(|e| anyhow!("line {} isn't JSON: {}", number + 1, e))?;
+        match value {
+            Value::String(target) => values.push(target),
+            Value::Object(object) => {
+                // Without a column, the key is picked from the first object.
+                if key.is_none() {
+                    let keys: Vec<String> = object.keys().cloned().collect();
+                    key = Some(keys[pick_column(&keys, None)?].clone());
+                }
+                match key.as_deref().and_then(|key| object.get(key)) {
+                    Some(Value::String(target)) => values.push(target.clone()),
+                    Some(Value::Number(target)) => values.push(target.to_string()),
+                    _ => {}
+                }
+            }
+            _ => return Err(anyhow!("line {} isn't a JSON object or string", number + 1)),
+        }
+    }
+    Ok(values)
+}
+
+fn pick_column(names: &[String], column: Option<&str>) -> Result<usize> {
+    if let Some(column) = column {
+        return names
+            .iter()
+            .position(|name| name == column)
+            .ok_or_else(|| anyhow!("no '{}' column, the columns are: {}", column, names.join(", ")));
+    }
+    if names.len() == 1 {
+        return Ok(0);
+    }
+    TARGET_COLUMNS
+        .iter()
+        .find_map(|candidate| names.iter().position(|name| name.eq_ignore_ascii_case(candidate)))
+        .ok_or_else(|| anyhow!("couldn't tell which column has the pages, pick one of {} with --columns", names.join(", ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_target_kinds() {
+        assert_eq!(Target::parse("123456"), Some(Target::PageId("123456".to_string())));
+        assert_eq!(Target::parse("https://www.facebook.com/somepage/"), Some(Target::Url("https://www.facebook.com/somepage".to_string())));
+        assert_eq!(Target::parse("facebook.com/somepage"), Some(Target::Url("facebook.com/somepage".to_string())));
+        assert_eq!(Target::parse(" Some Page "), Some(Target::Name("Some Page".to_string())));
+        assert_eq!(Target::parse("  "), None);
+    }
+
+    #[test]
+    fn test_parse_lines_dedups() {
+        let text = "# pages\nhttps://facebook.com/a\n\n123\nhttps://facebook.com/a/\n123\n";
+
+        let targets = parse_targets(text, InputFormat::detect(None, text, None), None).unwrap();
+
+        assert_eq!(targets, vec![Target::Url("https://facebook.com/a".to_string()), Target::PageId("123".to_string())]);
+    }
+
+    #[test]
+    fn test_parse_lines_dedups_same_page() {
+        let text = "https://www.facebook.com/SomePage/?ref=bookmarks\nhttps://m.facebook.com/somepage\n\
+                    https://www.facebook.com/profile.php?id=123456789\n123456789\nSomePage\n";
+
+        let targets = parse_targets(text, InputFormat::Lines, None).unwrap();
+
+        assert_eq!(targets, vec![
+            Target::Url("https://www.facebook.com/SomePage/?ref=bookmarks".to_string()),
+            Target::Url("https://www.facebook.com/profile.php?id=123456789".to_string()),
+            Target::Name("SomePage".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_csv_detects_column() {
+        let text = "name,Page_URL\nPage A,https://facebook.com/a\nPage B,\n";
+
+        let targets = parse_targets(text, InputFormat::Csv, None).unwrap();
+        assert_eq!(targets, vec![Target::Url("https://facebook.com/a".to_string())]);
+
+        let targets = parse_targets(text, InputFormat::Csv, Some("name")).unwrap();
+        assert_eq!(targets, vec![Target::Name("Page A".to_string()), Target::Name("Page B".to_string())]);
+
+        assert!(parse_targets("a,b\n1,2\n", InputFormat::Csv, None).is_err());
+        assert!(parse_targets(text, InputFormat::Csv, Some("missing")).is_err());
+    }
+
+    #[test]
+    fn test_parse_jsonl() {
+        let text = "{\"page_id\": 111, \"note\": \"x\"}\n\"https://facebook.com/b\"\n{\"page_id\": \"222\"}\n";
+
+        assert_eq!(InputFormat::detect(None, text, None), InputFormat::Jsonl);
+        let targets = parse_targets(text, InputFormat::Jsonl, None).unwrap();
+        assert_eq!(targets, vec![
+            Target::PageId("111".to_string()),
+            Target::Url("https://facebook.com/b".to_string()),
+            Target::PageId("222".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(InputFormat::detect(Some(Path::new("pages.csv")), "https://facebook.com/a", None), InputFormat::Csv);
+        assert_eq!(InputFormat::detect(Some(Path::new("pages.txt")), "url,name", None), InputFormat::Lines);
+        assert_eq!(InputFormat::detect(None, "url\nhttps://facebook.com/a", None), InputFormat::Csv);
+        assert_eq!(InputFormat::detect(None, "Name,\"Page URL\"\nA,https://facebook.com/a", None), InputFormat::Csv);
+        assert_eq!(InputFormat::detect(None, "https://facebook.com/a", None), InputFormat::Lines);
+        assert_eq!(InputFormat::detect(None, "handle,note\nsomepage,x", Some("handle")), InputFormat::Csv);
+    }
+
+    #[test]
+    fn test_detect_keeps_names_with_commas_as_lines() {
+        let text = "Smith, John\nAcme, Inc.\n";
+
+        assert_eq!(InputFormat::detect(None, text, None), InputFormat::Lines);
+        let targets = parse_targets(text, InputFormat::detect(None, text, None), None).unwrap();
+        assert_eq!(targets, vec![Target::Name("Smith, John".to_string()), Target::Name("Acme, Inc.".to_string())]);
+    }
+}
@@ -0,0 +1,265 @@
+use anyhow::{Result, anyhow};
+use chrono::NaiveDate;
+use std::str::FromStr;
+
+/// A file name with `{placeholder}`s filled in per output file, e.g.
+/// `{username}_{page_id}_{date}.{ext}`.
+///
+/// | Placeholder  | Value                                                  |
+/// |--------------|--------------------------------------------------------|
+/// | `{username}` | The page's vanity name from its URL (single, multi)    |
+/// | `{page_id}`  | The page ID (single, multi)                            |
+/// | `{query}`    | The search target or keyword query (search, keywords)  |
+/// | `{mode}`     | `single`, `multi`, `search` or `keywords`              |
+/// | `{date}`     | Today's date, `YYYY-MM-DD`                             |
+/// | `{ext}`      | The output format's extension                          |
+///
+/// ```
+/// use facebook_totem::{FilenameTemplate, FilenameValues};
+///
+/// let template: FilenameTemplate = "{username}_{page_id}.{ext}".parse().unwrap();
+/// let values = FilenameValues::new("multi", "csv").username("somepage").page_id("123");
+/// assert_eq!(template.render(&values).unwrap(), "somepage_123.csv");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilenameTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placeholder {
+    Username,
+    PageId,
+    Query,
+    Mode,
+    Date,
+    Ext,
+}
+
+impl Placeholder {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "username" => Some(Placeholder::Username),
+            "page_id" => Some(Placeholder::PageId),
+            "query" => Some(Placeholder::Query),
+            "mode" => Some(Placeholder::Mode),
+            "date" => Some(Placeholder::Date),
+            "ext" => Some(Placeholder::Ext),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Placeholder::Username => "username",
+            Placeholder::PageId => "page_id",
+            Placeholder::Query => "query",
+            Placeholder::Mode => "mode",
+            Placeholder::Date => "date",
+            Placeholder::Ext => "ext",
+        }
+    }
+}
+
+impl FromStr for FilenameTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(template: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| anyhow!("unclosed '{{' in filename template '{}'", template))?;
+            let name = &rest[start + 1..start + end];
+            let placeholder = Placeholder::parse(name)
+                .ok_or_else(|| anyhow!("unknown placeholder '{{{}}}' in filename template '{}'", name, template))?;
+            parts.push(Part::Placeholder(placeholder));
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        if parts.is_empty() {
+            return Err(anyhow!("filename template is empty"));
+        }
+        Ok(FilenameTemplate { parts })
+    }
+}
+
+/// What a [`FilenameTemplate`]'s placeholders are filled in with.
+#[derive(Debug, Clone)]
+pub struct FilenameValues<'a> {
+    mode: &'a str,
+    ext: &'a str,
+    date: NaiveDate,
+    username: Option<&'a str>,
+    page_id: Option<&'a str>,
+    query: Option<&'a str>,
+}
+
+impl<'a> FilenameValues<'a> {
+    /// Values for `mode` writing files with extension `ext`, dated today.
+    pub fn new(mode: &'a str, ext: &'a str) -> Self {
+        FilenameValues {
+            mode,
+            ext,
+            date: chrono::Local::now().date_naive(),
+            username: None,
+            page_id: None,
+            query: None,
+        }
+    }
+
+    pub fn date(mut self, date: NaiveDate) -> Self {
+        self.date = date;
+        self
+    }
+
+    pub fn username(mut self, username: &'a str) -> Self {
+        self.username = Some(username);
+        self
+    }
+
+    pub fn page_id(mut self, page_id: &'a str) -> Self {
+        self.page_id = Some(page_id);
+        self
+    }
+
+    pub fn query(mut self, query: &'a str) -> Self {
+        self.query = Some(query);
+        self
+    }
+}
+
+impl FilenameTemplate {
+    /// Whether files for different pages get different names, i.e. the
+    /// template has `{page_id}` or `{username}` in it.
+    pub fn names_pages(&self) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, Part::Placeholder(Placeholder::PageId | Placeholder::Username)))
+    }
+
+    /// Fill in the placeholders. Values are made safe for file names; a
+    /// placeholder the current mode has no value for is an error. An empty
+    /// value takes a `_`, `-` or space next to it along, so a page without a
+    /// username gets `123.csv` from `{username}_{page_id}.{ext}`.
+    pub fn render(&self, values: &FilenameValues) -> Result<String> {
+        let mut filename = String::new();
+        let mut drop_separator = false;
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => {
+                    let text = if drop_separator { text.strip_prefix(SEPARATORS).unwrap_or(text) } else { text };
+                    filename.push_str(text);
+                    drop_separator = false;
+                }
+                Part::Placeholder(placeholder) => {
+                    let value = match placeholder {
+                        Placeholder::Username => values.username.map(sanitize),
+                        Placeholder::PageId => values.page_id.map(sanitize),
+                        Placeholder::Query => values.query.map(sanitize),
+                        Placeholder::Mode => Some(sanitize(values.mode)),
+                        Placeholder::Ext => Some(sanitize(values.ext)),
+                        Placeholder::Date => Some(values.date.format("%Y-%m-%d").to_string()),
+                    };
+                    let value = value.ok_or_else(|| {
+                        anyhow!("{{{}}} can't be used in the filename template in {} mode", placeholder.name(), values.mode)
+                    })?;
+                    if !value.is_empty() {
+                        filename.push_str(&value);
+                        drop_separator = false;
+                    } else if filename.ends_with(SEPARATORS) {
+                        filename.pop();
+                    } else {
+                        drop_separator = true;
+                    }
+                }
+            }
+        }
+        Ok(filename)
+    }
+}
+
+/// What joins placeholders in a file name, dropped along with an empty value.
+const SEPARATORS: [char; 3] = ['_', '-', ' '];
+
+/// Replace characters that are path separators or awkward in file names.
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_template() {
+        let template: FilenameTemplate = "{username}_{page_id}_{date}.{ext}".parse().unwrap();
+        let values = FilenameValues::new("multi", "jsonl")
+            .username("somepage")
+            .page_id("123")
+            .date(NaiveDate::from_ymd_opt(2024, 3, 1).unwrap());
+
+        assert_eq!(template.render(&values).unwrap(), "somepage_123_2024-03-01.jsonl");
+    }
+
+    #[test]
+    fn test_render_sanitizes_values() {
+        let template: FilenameTemplate = "{mode}-{query}.{ext}".parse().unwrap();
+        let values = FilenameValues::new("keywords", "csv").query("heat pumps/solar?");
+
+        assert_eq!(template.render(&values).unwrap(), "keywords-heat pumps_solar_.csv");
+    }
+
+    #[test]
+    fn test_render_drops_separator_of_empty_value() {
+        let values = FilenameValues::new("multi", "csv").username("").page_id("123");
+        let render = |template: &str| template.parse::<FilenameTemplate>().unwrap().render(&values).unwrap();
+
+        assert_eq!(render("{username}_{page_id}.{ext}"), "123.csv");
+        assert_eq!(render("{page_id}_{username}.{ext}"), "123.csv");
+        assert_eq!(render("ads-{username}-{page_id}.{ext}"), "ads-123.csv");
+        assert_eq!(render("{page_id}_{mode}.{ext}"), "123_multi.csv");
+    }
+
+    #[test]
+    fn test_render_missing_value() {
+        let template: FilenameTemplate = "{page_id}.{ext}".parse().unwrap();
+        let err = template.render(&FilenameValues::new("search", "csv")).unwrap_err();
+
+        assert!(err.to_string().contains("{page_id}"));
+    }
+
+    #[test]
+    fn test_names_pages() {
+        assert!("{username}.{ext}".parse::<FilenameTemplate>().unwrap().names_pages());
+        assert!("ads/{page_id}.csv".parse::<FilenameTemplate>().unwrap().names_pages());
+        assert!(!"{mode}_{date}.{ext}".parse::<FilenameTemplate>().unwrap().names_pages());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!("{user}.csv".parse::<FilenameTemplate>().is_err());
+        assert!("{username.csv".parse::<FilenameTemplate>().is_err());
+        assert!("".parse::<FilenameTemplate>().is_err());
+        assert!("plain.csv".parse::<FilenameTemplate>().is_ok());
+    }
+}
@@ -123,6 +123,54 @@ fn test_cli_single_mode_syntax() {
     assert!(!stderr.contains("required") || stderr.contains("error") || stderr.contains("network"));
 }
 
+#[test]
+fn test_cli_query_filter_flags() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "single", "--help"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("--countries"));
+    assert!(stdout.contains("--ad-type"));
+    assert!(stdout.contains("--date-from"));
+
+    let output = Command::new("cargo")
+        .args(["run", "--", "--output", "test.csv", "single", "--url", "https://example.com", "--ad-type", "bogus"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("political_and_issue_ads"));
+}
+
+#[test]
+fn test_cli_graph_backend_requires_token() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "--output", "test.csv", "single", "--url", "https://example.com", "--backend", "graph"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--access-token"));
+}
+
+#[test]
+fn test_cli_keywords_mode_syntax() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "keywords", "--help"])
+        .output()
+        .expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("--query"));
+    assert!(stdout.contains("--exact"));
+}
+
 #[tokio::test]
 async fn test_search_mode_basic() {
     let temp_dir = TempDir::new().unwrap();
@@ -189,6 +237,1179 @@ mod integration_with_mocks {
     }
 }
 
+#[cfg(test)]
+mod graph_backend_with_mockito {
+    use super::*;
+    use mockito::Matcher;
+
+    #[tokio::test]
+    async fn test_graph_backend_paginates_page_ads() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+
+        let first_page = server
+            .mock("GET", "/v21.0/ads_archive")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("access_token".into(), "test-token".into()),
+                Matcher::UrlEncoded("search_page_ids".into(), r#"["123"]"#.into()),
+                Matcher::UrlEncoded("ad_reached_countries".into(), r#"["US"]"#.into()),
+            ]))
+            .with_body(r#"{"data":[{"id":"1","page_id":"123"}],"paging":{"cursors":{"after":"c1"},"next":"https://graph.facebook.com/next"}}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let second_page = server
+            .mock("GET", "/v21.0/ads_archive")
+            .match_query(Matcher::UrlEncoded("after".into(), "c1".into()))
+            .with_body(r#"{"data":[{"id":"2","page_id":"123"}],"paging":{"cursors":{"after":"c2"}}}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let backend = GraphApiBackend::new("test-token").base_url(&server.url());
+        let ads = backend.ads_for_page("123", &AdQuery::new().countries(["US"])).await?;
+
+        assert_eq!(ads.len(), 2);
+        assert_eq!(ads[0].ad_archive_id.as_deref(), Some("1"));
+        assert_eq!(ads[1].ad_archive_id.as_deref(), Some("2"));
+        assert_eq!(ads[1].page_id.as_deref(), Some("123"));
+        first_page.assert_async().await;
+        second_page.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_graph_backend_keyword_search_respects_max_ads() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+
+        let mock = server
+            .mock("GET", "/v21.0/ads_archive")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("search_terms".into(), "heat pumps".into()),
+                Matcher::UrlEncoded("search_type".into(), "KEYWORD_EXACT_PHRASE".into()),
+                Matcher::UrlEncoded("limit".into(), "1".into()),
+            ]))
+            .with_body(r#"{"data":[{"id":"1"}],"paging":{"cursors":{"after":"c1"},"next":"https://graph.facebook.com/next"}}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let backend = GraphApiBackend::new("test-token").base_url(&server.url());
+        let ads = backend
+            .ads_for_keywords("heat pumps", KeywordMatch::ExactPhrase, &AdQuery::new().max_ads(1))
+            .await?;
+
+        assert_eq!(ads.len(), 1);
+        mock.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_graph_backend_stops_when_cursor_repeats() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+
+        let mock = server
+            .mock("GET", "/v21.0/ads_archive")
+            .match_query(Matcher::Any)
+            .with_body(r#"{"data":[{"id":"1"}],"paging":{"cursors":{"after":"c1"},"next":"https://graph.facebook.com/next"}}"#)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let backend = GraphApiBackend::new("test-token").base_url(&server.url());
+        let ads = backend.ads_for_page("123", &AdQuery::new()).await?;
+        let none = backend.ads_for_page("123", &AdQuery::new().max_ads(0)).await?;
+
+        assert_eq!(ads.len(), 2);
+        assert!(none.is_empty());
+        mock.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_graph_backend_reports_api_errors() {
+        let mut server = mockito::Server::new_async().await;
+
+        server
+            .mock("GET", "/v21.0/ads_archive")
+            .match_query(Matcher::Any)
+            .with_status(400)
+            .with_body(r#"{"error":{"message":"Invalid OAuth access token.","type":"OAuthException","code":190}}"#)
+            .create_async()
+            .await;
+
+        let backend = GraphApiBackend::new("bad-token").base_url(&server.url());
+        let err = backend.ads_for_page("123", &AdQuery::new()).await.unwrap_err();
+
+        assert!(err.to_string().contains("Invalid OAuth access token."));
+    }
+}
+
+#[cfg(test)]
+mod scraper_flows_with_mockito {
+    use super::*;
+    use mockito::Matcher;
+
+    const PAGE_HTML: &str = r#"<html>some content[{"pageID":"123456789","other":"data"}]more content</html>"#;
+
+    fn mock_totem(server: &mockito::ServerGuard) -> Result<FacebookTotem> {
+        FacebookTotem::builder()
+            .base_url(&server.url())
+            .user_agent(UserAgentPolicy::Fixed("facebook_totem-tests".to_string()))
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_single_flow_follows_cursor() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+
+        server
+            .mock("GET", "/testpage")
+            .with_body(PAGE_HTML)
+            .create_async()
+            .await;
+        let first_page = server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::UrlEncoded("view_all_page_id".into(), "123456789".into()))
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"1"}]],"forwardCursor":"c1","collationToken":"t1","isResultComplete":false}}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let second_page = server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("forward_cursor".into(), "c1".into()),
+                Matcher::UrlEncoded("collation_token".into(), "t1".into()),
+            ]))
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"2"}]],"forwardCursor":"","isResultComplete":true}}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let totem = mock_totem(&server)?;
+        let id = totem.get_id_from_url(&format!("{}/testpage", server.url())).await?;
+        let ads = totem.get_ads_from_id(&id, &AdQuery::new()).await?;
+
+        assert_eq!(id, "123456789");
+        assert_eq!(ads.len(), 2);
+        assert_eq!(ads[1].ad_archive_id.as_deref(), Some("2"));
+        assert_eq!(ads[1].raw["adArchiveID"], "2");
+        first_page.assert_async().await;
+        second_page.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_id_from_url_with_id_skips_the_page_fetch() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let page = server.mock("GET", Matcher::Any).expect(0).create_async().await;
+
+        let totem = mock_totem(&server)?;
+        let id = totem.get_id_from_url("https://www.facebook.com/profile.php?id=100064123456789").await?;
+        let vanity_id = totem.get_id_from_url("https://m.facebook.com/Some-Page-123456789012/").await?;
+
+        assert_eq!(id, "100064123456789");
+        assert_eq!(vanity_id, "123456789012");
+        page.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_id_from_url_fetches_non_facebook_urls() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let page = server.mock("GET", "/profile.php").match_query(Matcher::Any).with_body(PAGE_HTML).create_async().await;
+
+        let totem = mock_totem(&server)?;
+        let id = totem.get_id_from_url(&format!("{}/profile.php?id=42", server.url())).await?;
+
+        assert_eq!(id, "123456789");
+        page.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stream_stops_when_cursor_repeats() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let search = server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::Any)
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"1"}]],"forwardCursor":"c1","isResultComplete":false}}"#)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let totem = mock_totem(&server)?;
+        let ads = totem.get_ads_from_id("123", &AdQuery::new()).await?;
+
+        assert_eq!(ads.len(), 2);
+        search.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_zero_max_ads_sends_no_request() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let search = server.mock("POST", Matcher::Any).expect(0).create_async().await;
+
+        let totem = mock_totem(&server)?;
+        let ads = totem.get_ads_from_id("123", &AdQuery::new().max_ads(0)).await?;
+
+        assert!(ads.is_empty());
+        search.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_id_from_url_reports_login_wall() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/somepage")
+            .with_status(302)
+            .with_header("Location", "/login/?next=%2Fsomepage")
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/login/")
+            .match_query(Matcher::Any)
+            .with_body(r#"<html><form id="login_form"></form></html>"#)
+            .create_async()
+            .await;
+
+        let totem = mock_totem(&server)?;
+        let err = totem.get_id_from_url(&format!("{}/somepage", server.url())).await.unwrap_err();
+
+        let facebook_error = err.downcast_ref::<FacebookError>().expect("a FacebookError");
+        assert_eq!(facebook_error, &FacebookError::LoginWall { url: Some(format!("{}/login/?next=%2Fsomepage", server.url())) });
+        assert!(err.to_string().contains("login wall"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_id_from_url_reports_login_wall_with_viewer_ids() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/somepage")
+            .with_status(302)
+            .with_header("Location", "/login.php")
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/login.php")
+            .with_body(r#"<html><script>{"userID":"100000000000001","page_id":"100000000000001"}</script></html>"#)
+            .create_async()
+            .await;
+
+        let totem = mock_totem(&server)?;
+        let err = totem.get_id_from_url(&format!("{}/somepage", server.url())).await.unwrap_err();
+
+        assert_eq!(err.downcast_ref::<FacebookError>(), Some(&FacebookError::LoginWall { url: Some(format!("{}/login.php", server.url())) }));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ads_error_payload_is_reported() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let search = server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::Any)
+            .with_body(r#"for (;;);{"__ar":1,"error":1357004,"errorSummary":"Sorry, something went wrong"}"#)
+            // There's no landing page, so the request had no session to
+            // refresh and isn't retried.
+            .expect(1)
+            .create_async()
+            .await;
+
+        let totem = mock_totem(&server)?;
+        let err = totem.get_ads_from_id("123", &AdQuery::new()).await.unwrap_err();
+
+        assert_eq!(err.downcast_ref::<FacebookError>(), Some(&FacebookError::ErrorPayload {
+            code: Some(1357004),
+            summary: Some("Sorry, something went wrong".to_string()),
+            description: None,
+        }));
+        search.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ads_stream_yields_pages_lazily() -> Result<()> {
+        use futures::StreamExt;
+
+        let mut server = mockito::Server::new_async().await;
+
+        let first_page = server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::UrlEncoded("view_all_page_id".into(), "123".into()))
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"1"},{"adArchiveID":"2"}]],"forwardCursor":"c1","isResultComplete":false}}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let second_page = server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::UrlEncoded("forward_cursor".into(), "c1".into()))
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"3"}]],"isResultComplete":true}}"#)
+            .expect(0)
+            .create_async()
+            .await;
+
+        let totem = mock_totem(&server)?;
+        let query = AdQuery::new();
+        let ads: Vec<FacebookAd> = totem.ads_stream("123", &query).take(2).map(Result::unwrap).collect().await;
+
+        assert_eq!(ads.len(), 2);
+        assert_eq!(ads[1].ad_archive_id.as_deref(), Some("2"));
+        first_page.assert_async().await;
+        second_page.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ads_stream_keeps_ads_before_an_error() -> Result<()> {
+        use futures::StreamExt;
+
+        let mut server = mockito::Server::new_async().await;
+
+        server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::UrlEncoded("view_all_page_id".into(), "123".into()))
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"1"}]],"forwardCursor":"c1","isResultComplete":false}}"#)
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::UrlEncoded("forward_cursor".into(), "c1".into()))
+            .with_body("not json")
+            .create_async()
+            .await;
+
+        let totem = mock_totem(&server)?;
+        let query = AdQuery::new();
+        let results: Vec<Result<FacebookAd>> = totem.ads_stream("123", &query).collect().await;
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].as_ref().unwrap().ad_archive_id.as_deref(), Some("1"));
+        assert!(results[1].is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_flow() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+
+        let mock = server
+            .mock("POST", "/ads/library/async/search_typeahead/")
+            .match_query(Matcher::UrlEncoded("q".into(), "Test Page".into()))
+            .match_header("user-agent", "facebook_totem-tests")
+            .with_body(r#"for (;;);{"payload":{"pageResults":[{"pageID":"123","pageName":"Test Page","pageProfilePictureURI":"test.jpg","pageURI":"test"}]}}"#)
+            .create_async()
+            .await;
+
+        let pages = mock_totem(&server)?.get_facebook_page_from_name("Test Page").await?;
+
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].page_id, Some("123".to_string()));
+        mock.assert_async().await;
+        Ok(())
+    }
+
+    const LANDING_PAGE: &str = r#"<html>["LSD",[],{"token":"AVqToken"},323]{"__spin_r":1012345678,"__spin_t":1700000000,"hsi":"730"}</html>"#;
+
+    #[tokio::test]
+    async fn test_session_tokens_bootstrapped_once_and_reused() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+
+        let landing_page = server
+            .mock("GET", "/ads/library/")
+            .with_body(LANDING_PAGE)
+            .expect(1)
+            .create_async()
+            .await;
+        let search_ads = server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::Any)
+            .match_header("x-fb-lsd", "AVqToken")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("lsd".into(), "AVqToken".into()),
+                Matcher::UrlEncoded("__spin_r".into(), "1012345678".into()),
+                Matcher::UrlEncoded("__spin_t".into(), "1700000000".into()),
+                Matcher::UrlEncoded("__hsi".into(), "730".into()),
+            ]))
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"1"}]],"isResultComplete":true}}"#)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let totem = mock_totem(&server)?;
+        totem.get_ads_from_id("123", &AdQuery::new()).await?;
+        let ads = totem.get_ads_from_id("456", &AdQuery::new()).await?;
+
+        assert_eq!(ads.len(), 1);
+        landing_page.assert_async().await;
+        search_ads.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failed_bootstrap_falls_back_to_tokenless_form() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+
+        let landing_page = server
+            .mock("GET", "/ads/library/")
+            .with_status(500)
+            .with_body(LANDING_PAGE)
+            .expect(1)
+            .create_async()
+            .await;
+        let search_ads = server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::Any)
+            .match_body(Matcher::UrlEncoded("lsd".into(), "\"\"".into()))
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"1"}]],"isResultComplete":true}}"#)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let totem = mock_totem(&server)?;
+        totem.get_ads_from_id("123", &AdQuery::new()).await?;
+        let ads = totem.get_ads_from_id("456", &AdQuery::new()).await?;
+
+        // The failed bootstrap was cached, so the second pull didn't retry it.
+        assert_eq!(ads.len(), 1);
+        landing_page.assert_async().await;
+        search_ads.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_session_refreshed_after_error_payload() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+
+        let landing_page = server
+            .mock("GET", "/ads/library/")
+            .with_body(LANDING_PAGE)
+            .expect(2)
+            .create_async()
+            .await;
+        let expired = server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::Any)
+            .with_body(r#"for (;;);{"__ar":1,"error":1357004,"errorSummary":"Sorry, something went wrong"}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let retried = server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::Any)
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"1"}]],"isResultComplete":true}}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let ads = mock_totem(&server)?.get_ads_from_id("123", &AdQuery::new()).await?;
+
+        assert_eq!(ads.len(), 1);
+        landing_page.assert_async().await;
+        expired.assert_async().await;
+        retried.assert_async().await;
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_single_against_mock_server() -> Result<()> {
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/testpage").with_body(PAGE_HTML).create();
+        server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::Any)
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"42","pageName":"Test Page"}]],"isResultComplete":true}}"#)
+            .create();
+
+        let temp_dir = TempDir::new()?;
+        let output = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+            .args([
+                "--output", "ads.csv",
+                "--base-url", &server.url(),
+                "--user-agent", "facebook_totem-tests",
+                "single", "--url", &format!("{}/testpage", server.url()),
+            ])
+            .current_dir(&temp_dir)
+            .output()?;
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let content = fs::read_to_string(temp_dir.path().join("output/ads.csv"))?;
+        assert!(content.contains("adArchiveID"));
+        assert!(content.contains("42"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_single_flattened_against_mock_server() -> Result<()> {
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/testpage").with_body(PAGE_HTML).create();
+        server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::Any)
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"42","snapshot":{"body":{"text":"Hello"},"cards":[{"title":"A"},{"title":"B"}]}}]],"isResultComplete":true}}"#)
+            .create();
+
+        let temp_dir = TempDir::new()?;
+        let output = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+            .args([
+                "--output", "ads.csv",
+                "--base-url", &server.url(),
+                "--user-agent", "facebook_totem-tests",
+                "--flatten", "--arrays", "explode",
+                "single", "--url", &format!("{}/testpage", server.url()),
+            ])
+            .current_dir(&temp_dir)
+            .output()?;
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let content = fs::read_to_string(temp_dir.path().join("output/ads.csv"))?;
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines[0], "adArchiveID,snapshot.body.text,snapshot.cards.title");
+        assert_eq!(lines[1], "42,Hello,A");
+        assert_eq!(lines[2], "42,Hello,B");
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_single_with_fields_against_mock_server() -> Result<()> {
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/testpage").with_body(PAGE_HTML).create();
+        server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::Any)
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"42","pageName":"Test Page","snapshot":{"title":"Hello"}}]],"isResultComplete":true}}"#)
+            .create();
+
+        let run = |fields: &str, temp_dir: &TempDir| {
+            Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+                .args([
+                    "--output", "ads.csv",
+                    "--base-url", &server.url(),
+                    "--user-agent", "facebook_totem-tests",
+                    "--fields", fields,
+                    "single", "--url", &format!("{}/testpage", server.url()),
+                ])
+                .current_dir(temp_dir)
+                .output()
+        };
+
+        let temp_dir = TempDir::new()?;
+        let output = run("snapshot.title:title,adArchiveID", &temp_dir)?;
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let content = fs::read_to_string(temp_dir.path().join("output/ads.csv"))?;
+        assert_eq!(content.lines().collect::<Vec<_>>(), vec!["title,adArchiveID", "Hello,42"]);
+
+        let temp_dir = TempDir::new()?;
+        let output = run("adArchiveID,snapshot.titel", &temp_dir)?;
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("snapshot.titel"));
+        assert!(!temp_dir.path().join("output/ads.csv").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_single_jsonl_against_mock_server() -> Result<()> {
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/testpage").with_body(PAGE_HTML).create();
+        server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::Any)
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"42","isActive":true},{"adArchiveID":"43","snapshot":{"cards":[]}}]],"isResultComplete":true}}"#)
+            .create();
+
+        let temp_dir = TempDir::new()?;
+        let output = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+            .args([
+                "--output", "ads.jsonl",
+                "--base-url", &server.url(),
+                "--user-agent", "facebook_totem-tests",
+                "--format", "jsonl",
+                "single", "--url", &format!("{}/testpage", server.url()),
+            ])
+            .current_dir(&temp_dir)
+            .output()?;
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let content = fs::read_to_string(temp_dir.path().join("output/ads.jsonl"))?;
+        assert_eq!(content.lines().collect::<Vec<_>>(), vec![
+            r#"{"adArchiveID":"42","isActive":true}"#,
+            r#"{"adArchiveID":"43","snapshot":{"cards":[]}}"#,
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_single_jsonl_to_stdout() -> Result<()> {
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/testpage").with_body(PAGE_HTML).create();
+        server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::Any)
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"42"},{"adArchiveID":"43"}]],"isResultComplete":true}}"#)
+            .create();
+
+        let temp_dir = TempDir::new()?;
+        let output = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+            .args([
+                "--output", "-",
+                "--base-url", &server.url(),
+                "--user-agent", "facebook_totem-tests",
+                "--format", "jsonl",
+                "single", "--url", &format!("{}/testpage", server.url()),
+            ])
+            .current_dir(&temp_dir)
+            .output()?;
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(String::from_utf8(output.stdout)?, "{\"adArchiveID\":\"42\"}\n{\"adArchiveID\":\"43\"}\n");
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Getting ads for page ID: 123456789"));
+        assert!(!temp_dir.path().join("output").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_multi_with_output_dir_and_filename_template() -> Result<()> {
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/testpage").with_body(PAGE_HTML).create();
+        server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::Any)
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"42"}]],"isResultComplete":true}}"#)
+            .create();
+
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("pages.csv"), format!("url\n{}/testpage\n", server.url()))?;
+        let output = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+            .args([
+                "--output", "unused",
+                "--base-url", &server.url(),
+                "--user-agent", "facebook_totem-tests",
+                "--output-dir", "results",
+                "--filename-template", "{mode}/{page_id}.{ext}",
+                "multi", "--urls", "pages.csv", "--columns", "url",
+            ])
+            .current_dir(&temp_dir)
+            .output()?;
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let content = fs::read_to_string(temp_dir.path().join("results/multi/123456789.csv"))?;
+        assert!(content.contains("42"));
+        assert!(!temp_dir.path().join("output").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_multi_concurrent_against_mock_server() -> Result<()> {
+        let mut server = mockito::Server::new();
+        for id in ["111", "222", "333"] {
+            server
+                .mock("GET", format!("/page{}", id).as_str())
+                .with_body(format!(r#"<html>[{{"pageID":"{}"}}]</html>"#, id))
+                .create();
+        }
+        server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::Any)
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"42"}]],"isResultComplete":true}}"#)
+            .create();
+
+        let temp_dir = TempDir::new()?;
+        let urls: Vec<String> = ["111", "222", "333"].iter().map(|id| format!("{}/page{}", server.url(), id)).collect();
+        fs::write(temp_dir.path().join("pages.csv"), format!("url\n{}\n", urls.join("\n")))?;
+        let output = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+            .args([
+                "--output", "unused",
+                "--base-url", &server.url(),
+                "--user-agent", "facebook_totem-tests",
+                "--filename-template", "{page_id}.{ext}",
+                "--requests-per-second", "100",
+                "multi", "--urls", "pages.csv", "--columns", "url", "--concurrency", "3",
+            ])
+            .current_dir(&temp_dir)
+            .output()?;
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        for id in ["111", "222", "333"] {
+            let content = fs::read_to_string(temp_dir.path().join(format!("output/{}.csv", id)))?;
+            assert!(content.contains("42"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_multi_resume_retries_failed_targets() -> Result<()> {
+        let mut server = mockito::Server::new();
+        let done_page = server.mock("GET", "/page111").with_body(r#"<html>[{"pageID":"111"}]</html>"#).expect(1).create();
+        let blocked_page = server.mock("GET", "/page222").with_body("<html>Log in to continue</html>").create();
+        server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::Any)
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"42"}]],"isResultComplete":true}}"#)
+            .create();
+
+        let temp_dir = TempDir::new()?;
+        let base_url = server.url();
+        fs::write(temp_dir.path().join("pages.csv"), format!("url\n{0}/page111\n{0}/page222\n", base_url))?;
+        let run = |resume: bool| -> Result<std::process::Output> {
+            let mut command = Command::new(env!("CARGO_BIN_EXE_facebook_totem"));
+            command
+                .args([
+                    "--output", "unused",
+                    "--base-url", &base_url,
+                    "--user-agent", "facebook_totem-tests",
+                    "--filename-template", "{page_id}.{ext}",
+                    "multi", "--urls", "pages.csv", "--columns", "url",
+                ])
+                .current_dir(&temp_dir);
+            if resume {
+                command.arg("--resume");
+            }
+            Ok(command.output()?)
+        };
+
+        let output = run(false)?;
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("1 with ads, 0 without ads, 1 failed, 0 skipped"));
+        let checkpoint = fs::read_to_string(temp_dir.path().join("output/pages.checkpoint.jsonl"))?;
+        assert!(checkpoint.contains(r#""status":"done","page_id":"111","ads":1"#), "{}", checkpoint);
+        assert!(checkpoint.contains(r#""status":"id_not_found""#), "{}", checkpoint);
+        let report = fs::read_to_string(temp_dir.path().join("output/pages.report.csv"))?;
+        assert_eq!(report.lines().collect::<Vec<_>>(), vec![
+            "target,status,page_id,ads,error".to_string(),
+            format!("{}/page111,done,111,1,", base_url),
+            format!("{}/page222,id_not_found,,0,Could not find pageID in response", base_url),
+        ]);
+
+        blocked_page.remove();
+        server.mock("GET", "/page222").with_body(r#"<html>[{"pageID":"222"}]</html>"#).create();
+        let output = run(true)?;
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert!(String::from_utf8_lossy(&output.stdout).contains("1 targets already done"));
+        done_page.assert();
+        assert!(temp_dir.path().join("output/222.csv").exists());
+        let checkpoint = fs::read_to_string(temp_dir.path().join("output/pages.checkpoint.jsonl"))?;
+        assert!(checkpoint.contains(r#""status":"done","page_id":"222""#), "{}", checkpoint);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_multi_reports_http_errors_as_json() -> Result<()> {
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/page111").with_status(500).create();
+
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("pages.csv"), format!("url\n{}/page111\n", server.url()))?;
+        let output = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+            .args([
+                "--output", "unused",
+                "--base-url", &server.url(),
+                "--user-agent", "facebook_totem-tests",
+                "multi", "--urls", "pages.csv", "--columns", "url", "--report", "report.json",
+            ])
+            .current_dir(&temp_dir)
+            .output()?;
+
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("1 of 1 targets failed, see report.json"));
+        let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(temp_dir.path().join("report.json"))?)?;
+        assert_eq!(report[0]["status"], "http_error");
+        assert!(report[0]["error"].as_str().unwrap().contains("500"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_multi_reads_mixed_targets_from_stdin() -> Result<()> {
+        use std::io::Write;
+        use std::process::Stdio;
+
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/page111").with_body(r#"<html>[{"pageID":"111"}]</html>"#).expect(1).create();
+        server
+            .mock("POST", "/ads/library/async/search_typeahead/")
+            .match_query(Matcher::Any)
+            .with_body(r#"for (;;);{"payload":{"pageResults":[{"pageID":"123","pageName":"Test Page"}]}}"#)
+            .create();
+        server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::Any)
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"42"}]],"isResultComplete":true}}"#)
+            .create();
+
+        let temp_dir = TempDir::new()?;
+        let mut child = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+            .args([
+                "--output", "unused",
+                "--base-url", &server.url(),
+                "--user-agent", "facebook_totem-tests",
+                "--filename-template", "{page_id}.{ext}",
+                "multi", "--urls", "-",
+            ])
+            .current_dir(&temp_dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        write!(child.stdin.take().unwrap(), "{0}/page111\n222\nTest Page\n{0}/page111/\n", server.url())?;
+        let output = child.wait_with_output()?;
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert!(String::from_utf8_lossy(&output.stdout).contains("3 targets found"));
+        for id in ["111", "222", "123"] {
+            assert!(temp_dir.path().join(format!("output/{}.csv", id)).exists(), "no file for {}", id);
+        }
+        assert!(temp_dir.path().join("output/stdin.report.csv").exists());
+        Ok(())
+    }
+
+    fn mock_two_pages(server: &mut mockito::ServerGuard) {
+        for id in ["111", "222"] {
+            server
+                .mock("GET", format!("/page{}", id).as_str())
+                .with_body(format!(r#"<html>[{{"pageID":"{}"}}]</html>"#, id))
+                .create();
+            server
+                .mock("POST", "/ads/library/async/search_ads/")
+                .match_query(Matcher::UrlEncoded("view_all_page_id".into(), id.into()))
+                .with_body(format!(r#"for (;;);{{"payload":{{"results":[[{{"adArchiveID":"{0}0","pageID":"{0}"}}]],"isResultComplete":true}}}}"#, id))
+                .create();
+        }
+    }
+
+    #[test]
+    fn test_cli_multi_default_names_without_username() -> Result<()> {
+        let mut server = mockito::Server::new();
+        mock_two_pages(&mut server);
+
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("pages.txt"), "222\n")?;
+        let output = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+            .args(["--output", "unused.csv", "--base-url", &server.url(), "--user-agent", "facebook_totem-tests", "multi", "--urls", "pages.txt"])
+            .current_dir(&temp_dir)
+            .output()?;
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert!(temp_dir.path().join("output/222.csv").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_multi_merge_into_one_csv() -> Result<()> {
+        let mut server = mockito::Server::new();
+        mock_two_pages(&mut server);
+
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("pages.txt"), format!("{0}/page111\n222\n", server.url()))?;
+        let output = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+            .args([
+                "--output", "watchlist.csv",
+                "--base-url", &server.url(),
+                "--user-agent", "facebook_totem-tests",
+                "--fields", "source_target,page_id,username,adArchiveID",
+                "multi", "--urls", "pages.txt", "--merge",
+            ])
+            .current_dir(&temp_dir)
+            .output()?;
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let content = fs::read_to_string(temp_dir.path().join("output/watchlist.csv"))?;
+        assert_eq!(content.lines().collect::<Vec<_>>(), vec![
+            "source_target,page_id,username,adArchiveID".to_string(),
+            format!("{}/page111,111,,1110", server.url()),
+            "222,222,,2220".to_string(),
+        ]);
+        Ok(())
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_cli_multi_merge_into_sqlite_records_targets() -> Result<()> {
+        let mut server = mockito::Server::new();
+        mock_two_pages(&mut server);
+
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("pages.txt"), format!("{0}/page111\n222\n", server.url()))?;
+        let output = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+            .args([
+                "--output", "watchlist.sqlite",
+                "--base-url", &server.url(),
+                "--user-agent", "facebook_totem-tests",
+                "--format", "sqlite",
+                "multi", "--urls", "pages.txt", "--merge",
+            ])
+            .current_dir(&temp_dir)
+            .output()?;
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let store = facebook_totem::SqliteStore::open(temp_dir.path().join("output/watchlist.sqlite"))?;
+        let rows: Vec<(String, String)> = store
+            .connection()
+            .prepare("SELECT source_target, ad_archive_id FROM targets JOIN ads USING (page_id) ORDER BY ad_archive_id")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        assert_eq!(rows, vec![
+            (format!("{}/page111", server.url()), "1110".to_string()),
+            ("222".to_string(), "2220".to_string()),
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_rejects_unknown_template_placeholder() -> Result<()> {
+        let output = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+            .args(["--output", "ads.csv", "--filename-template", "{user}.csv", "search", "--target", "x"])
+            .output()?;
+
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("unknown placeholder '{user}'"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_rejects_bad_dates() -> Result<()> {
+        let mut server = mockito::Server::new();
+        let requests = server.mock("GET", Matcher::Any).expect(0).create();
+
+        let temp_dir = TempDir::new()?;
+        for ([date_from, date_to], error) in [
+            (["2024-13-01", "2024-03-01"], "YYYY-MM-DD"),
+            (["2024-03-01", "01/03/2024"], "YYYY-MM-DD"),
+            (["2024-03-02", "2024-03-01"], "after it ends on 2024-03-01"),
+        ] {
+            let output = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+                .args([
+                    "--output", "ads.csv",
+                    "--base-url", &server.url(),
+                    "--user-agent", "facebook_totem-tests",
+                    "single", "--url", &format!("{}/testpage", server.url()),
+                    "--date-from", date_from, "--date-to", date_to,
+                ])
+                .current_dir(&temp_dir)
+                .output()?;
+
+            assert!(!output.status.success());
+            assert!(String::from_utf8_lossy(&output.stderr).contains(error), "{}", String::from_utf8_lossy(&output.stderr));
+        }
+        requests.assert();
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_rejects_zero_flatten_depth() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let output = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+            .args(["--output", "ads.csv", "--user-agent", "facebook_totem-tests", "--flatten", "--flatten-depth", "0", "single", "--url", "123"])
+            .current_dir(&temp_dir)
+            .output()?;
+
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("--flatten-depth"), "{}", String::from_utf8_lossy(&output.stderr));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_graph_backend_rejects_base_url() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let output = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+            .args([
+                "--output", "ads.csv",
+                "--base-url", "http://127.0.0.1:9",
+                "--user-agent", "facebook_totem-tests",
+                "single", "--url", "123456789", "--backend", "graph", "--access-token", "token",
+            ])
+            .current_dir(&temp_dir)
+            .output()?;
+
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("--base-url"), "{}", String::from_utf8_lossy(&output.stderr));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_multi_merge_resume_keeps_merged_file() -> Result<()> {
+        let mut server = mockito::Server::new();
+        let page = server.mock("GET", Matcher::Any).expect(0).create();
+
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("pages.txt"), format!("{}/page111\n222\n", server.url()))?;
+        fs::create_dir(temp_dir.path().join("output"))?;
+        fs::write(temp_dir.path().join("output/watchlist.csv"), "adArchiveID\n1110\n")?;
+        let output = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+            .args([
+                "--output", "watchlist.csv",
+                "--base-url", &server.url(),
+                "--user-agent", "facebook_totem-tests",
+                "multi", "--urls", "pages.txt", "--merge", "--resume",
+            ])
+            .current_dir(&temp_dir)
+            .output()?;
+
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("--merge --resume"), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(fs::read_to_string(temp_dir.path().join("output/watchlist.csv"))?, "adArchiveID\n1110\n");
+        page.assert();
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_multi_checks_template_before_fetching() -> Result<()> {
+        let mut server = mockito::Server::new();
+        let page = server.mock("GET", Matcher::Any).expect(0).create();
+
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("pages.txt"), format!("{}/page111\n", server.url()))?;
+        for (template, error) in [("{mode}.{ext}", "needs {page_id} or {username}"), ("{page_id}_{query}.{ext}", "{query}")] {
+            let output = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+                .args([
+                    "--output", "ads.csv",
+                    "--base-url", &server.url(),
+                    "--user-agent", "facebook_totem-tests",
+                    "--filename-template", template,
+                    "multi", "--urls", "pages.txt",
+                ])
+                .current_dir(&temp_dir)
+                .output()?;
+
+            assert!(!output.status.success());
+            assert!(String::from_utf8_lossy(&output.stderr).contains(error), "{}", String::from_utf8_lossy(&output.stderr));
+        }
+        page.assert();
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_saves_fetched_ads_when_pagination_fails() -> Result<()> {
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/testpage").with_body(PAGE_HTML).create();
+        server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::UrlEncoded("view_all_page_id".into(), "123456789".into()))
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"42"},{"adArchiveID":"43"}]],"forwardCursor":"c1","isResultComplete":false}}"#)
+            .create();
+        server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::UrlEncoded("forward_cursor".into(), "c1".into()))
+            .with_status(500)
+            .with_body("not json")
+            .create();
+
+        let temp_dir = TempDir::new()?;
+        let output = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+            .args([
+                "--output", "ads.csv",
+                "--base-url", &server.url(),
+                "--user-agent", "facebook_totem-tests",
+                "single", "--url", &format!("{}/testpage", server.url()),
+            ])
+            .current_dir(&temp_dir)
+            .output()?;
+
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("stopped after 2 ads"));
+        let content = fs::read_to_string(temp_dir.path().join("output/ads.csv"))?;
+        assert_eq!(content.lines().collect::<Vec<_>>(), vec!["adArchiveID", "42", "43"]);
+        Ok(())
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_cli_sqlite_accumulates_across_runs() -> Result<()> {
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/testpage").with_body(PAGE_HTML).create();
+        server
+            .mock("POST", "/ads/library/async/search_typeahead/")
+            .match_query(Matcher::Any)
+            .with_body(r#"for (;;);{"payload":{"pageResults":[{"pageID":"123456789","pageName":"Test Page","pageProfilePictureURI":"test.jpg","pageURI":"testpage"}]}}"#)
+            .create();
+        server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::Any)
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"42","pageID":"123456789","snapshot":{"cards":[{"title":"A"},{"title":"B"}]}}]],"isResultComplete":true}}"#)
+            .create();
+
+        let temp_dir = TempDir::new()?;
+        let run = |args: &[&str]| -> Result<()> {
+            let output = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+                .args(["--output", "ads.sqlite", "--base-url", &server.url(), "--user-agent", "facebook_totem-tests", "--format", "sqlite"])
+                .args(args)
+                .current_dir(&temp_dir)
+                .output()?;
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+            Ok(())
+        };
+        let page_url = format!("{}/testpage", server.url());
+        run(&["search", "--target", "Test Page"])?;
+        run(&["single", "--url", &page_url])?;
+        run(&["single", "--url", &page_url])?;
+
+        let store = facebook_totem::SqliteStore::open(temp_dir.path().join("output/ads.sqlite"))?;
+        let count = |table: &str| -> rusqlite::Result<i64> {
+            store.connection().query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0))
+        };
+        assert_eq!((count("pages")?, count("ads")?, count("ad_cards")?), (1, 1, 2));
+        let page_uri: String = store.connection().query_row("SELECT page_uri FROM pages", [], |row| row.get(0))?;
+        assert_eq!(page_uri, "testpage");
+        Ok(())
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_cli_single_parquet_against_mock_server() -> Result<()> {
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/testpage").with_body(PAGE_HTML).create();
+        server
+            .mock("POST", "/ads/library/async/search_ads/")
+            .match_query(Matcher::Any)
+            .with_body(r#"for (;;);{"payload":{"results":[[{"adArchiveID":"42","startDate":1700000000,"spend":"<100"}]],"isResultComplete":true}}"#)
+            .create();
+
+        let temp_dir = TempDir::new()?;
+        let output = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+            .args([
+                "--output", "ads.parquet",
+                "--base-url", &server.url(),
+                "--user-agent", "facebook_totem-tests",
+                "--format", "parquet",
+                "single", "--url", &format!("{}/testpage", server.url()),
+            ])
+            .current_dir(&temp_dir)
+            .output()?;
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let content = fs::read(temp_dir.path().join("output/ads.parquet"))?;
+        assert!(content.starts_with(b"PAR1") && content.ends_with(b"PAR1"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_search_against_mock_server() -> Result<()> {
+        let mut server = mockito::Server::new();
+        server
+            .mock("POST", "/ads/library/async/search_typeahead/")
+            .match_query(Matcher::Any)
+            .with_body(r#"for (;;);{"payload":{"pageResults":[{"pageID":"123","pageName":"Test Page","pageProfilePictureURI":"test.jpg","pageURI":"test"}]}}"#)
+            .create();
+
+        let temp_dir = TempDir::new()?;
+        let output = Command::new(env!("CARGO_BIN_EXE_facebook_totem"))
+            .args([
+                "--output", "pages.csv",
+                "--base-url", &server.url(),
+                "--user-agent", "facebook_totem-tests",
+                "search", "--target", "Test Page",
+            ])
+            .current_dir(&temp_dir)
+            .output()?;
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let content = fs::read_to_string(temp_dir.path().join("output/pages.csv"))?;
+        assert!(content.contains("Test Page"));
+        Ok(())
+    }
+}
+
 // Test utilities and helper functions
 #[cfg(test)]
 mod test_utilities {